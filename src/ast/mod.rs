@@ -1,6 +1,7 @@
 
 use std;
 use identifier::Identifier;
+use location::Spanned;
 
 pub mod statements;
 pub mod expressions;
@@ -58,7 +59,7 @@ impl Expression for Identifier
 #[deriving(Show)]
 pub struct Ast
 {
-    pub nodes: Vec<Stmt>,
+    pub nodes: Vec<Spanned<Stmt>>,
 }
 
 impl Ast
@@ -1,10 +1,11 @@
 
 use ast;
+use location::Spanned;
 
 #[deriving(Show)]
 pub struct Block
 {
-    statements: Vec<ast::Stmt>,
+    statements: Vec<Spanned<ast::Stmt>>,
 }
 
 impl ast::Statement for Block
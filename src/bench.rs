@@ -0,0 +1,46 @@
+
+//! Tokenizer benchmarks, run with `cargo bench`.
+//!
+//! The input is the ATmega328P header we ship with, which is about the size
+//! and shape of a typical vendor header.
+
+use test::Bencher;
+use token::Tokenizer;
+
+static HEADER: &'static str = include_str!("../iom328p.h");
+
+/// Reads every token, copying its spelling into an owned `Token`.
+#[bench]
+fn owned_tokens(b: &mut Bencher)
+{
+    b.bytes = HEADER.len() as u64;
+    
+    b.iter(|| {
+        let mut count = 0u;
+        
+        for token in Tokenizer::new(HEADER) {
+            assert!(token.is_ok());
+            count += 1;
+        }
+        
+        count
+    });
+}
+
+/// Reads every token as a lexeme, which borrows its spelling from the source.
+#[bench]
+fn borrowed_lexemes(b: &mut Bencher)
+{
+    b.bytes = HEADER.len() as u64;
+    
+    b.iter(|| {
+        let mut count = 0u;
+        
+        for lexeme in Tokenizer::new(HEADER).lexemes() {
+            assert!(lexeme.is_ok());
+            count += 1;
+        }
+        
+        count
+    });
+}
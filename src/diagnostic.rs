@@ -0,0 +1,243 @@
+
+use std;
+use location::Span;
+
+/// How serious a diagnostic is.
+#[deriving(Clone,PartialEq,Eq,PartialOrd,Ord)]
+pub enum Severity
+{
+    Note,
+    Warning,
+    Error,
+    /// An error which stops any further processing.
+    Fatal,
+}
+
+impl std::fmt::Show for Severity
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(),std::fmt::Error>
+    {
+        match self {
+            &Severity::Note => "note",
+            &Severity::Warning => "warning",
+            &Severity::Error => "error",
+            &Severity::Fatal => "fatal error",
+        }.fmt(formatter)
+    }
+}
+
+/// The kind of problem a diagnostic describes.
+/// Tools should match on this rather than on the message text.
+#[deriving(Clone,PartialEq,Eq,Hash,Show)]
+pub enum Code
+{
+    /// The end of the file was reached in the middle of something.
+    UnexpectedEndOfFile,
+    /// A token was found where a different one was expected.
+    UnexpectedToken,
+    /// A character which doesn't start any token.
+    UnknownCharacter,
+    InvalidIdentifier,
+    UnterminatedLiteral,
+    UnterminatedComment,
+    EmptyCharacterConstant,
+    /// A character or escape sequence which doesn't fit in the type of its literal.
+    CharacterOutOfRange,
+    InvalidEscapeSequence,
+    InvalidUniversalCharacter,
+    InvalidNumericLiteral,
+    UnknownDirective,
+    /// The source couldn't be read.
+    ReadError,
+    /// The source isn't valid UTF-8.
+    InvalidEncoding,
+    /// A function-like macro has two parameters with the same name.
+    DuplicateMacroParameter,
+    /// A macro was defined again with a different body.
+    MacroRedefined,
+    /// The arguments of a function-like macro were never closed.
+    UnterminatedMacroInvocation,
+    /// A function-like macro was given the wrong number of arguments.
+    MacroArgumentCount,
+    /// A '#' or '##' operator which can't be carried out.
+    InvalidMacroOperator,
+    /// `__VA_ARGS__` or `__VA_OPT__` used outside of a variadic macro.
+    InvalidVariadicUse,
+    /// An '#if' without a matching '#endif'.
+    UnterminatedConditional,
+    /// An '#elif', '#else' or '#endif' which doesn't belong to an '#if'.
+    UnmatchedConditional,
+    /// A preprocessor expression which can't be evaluated, such as one dividing by zero.
+    InvalidConstantExpression,
+    /// An '#include' which isn't followed by a header name.
+    InvalidHeaderName,
+    /// A header which isn't in any of the search paths.
+    HeaderNotFound,
+    /// Includes nested more deeply than allowed, which usually means a file includes itself.
+    IncludeDepthExceeded,
+    /// The message of an '#error' directive.
+    ErrorDirective,
+    /// The message of a '#warning' directive.
+    WarningDirective,
+    /// A '#line' directive which isn't followed by a line number and optional file name.
+    InvalidLineDirective,
+}
+
+impl Code
+{
+    /// Gets the stable identifier of the code, such as "E0001".
+    pub fn id(&self) -> &'static str
+    {
+        match *self {
+            Code::UnexpectedEndOfFile => "E0001",
+            Code::UnexpectedToken => "E0002",
+            Code::UnknownCharacter => "E0003",
+            Code::InvalidIdentifier => "E0004",
+            Code::UnterminatedLiteral => "E0005",
+            Code::EmptyCharacterConstant => "E0006",
+            Code::InvalidEscapeSequence => "E0007",
+            Code::InvalidUniversalCharacter => "E0008",
+            Code::InvalidNumericLiteral => "E0009",
+            Code::UnknownDirective => "E0010",
+            Code::UnterminatedComment => "E0011",
+            Code::CharacterOutOfRange => "E0012",
+            Code::ReadError => "E0013",
+            Code::InvalidEncoding => "E0014",
+            Code::DuplicateMacroParameter => "E0015",
+            Code::MacroRedefined => "E0016",
+            Code::UnterminatedMacroInvocation => "E0017",
+            Code::MacroArgumentCount => "E0018",
+            Code::InvalidMacroOperator => "E0019",
+            Code::InvalidVariadicUse => "E0020",
+            Code::UnterminatedConditional => "E0021",
+            Code::UnmatchedConditional => "E0022",
+            Code::InvalidConstantExpression => "E0023",
+            Code::InvalidHeaderName => "E0024",
+            Code::HeaderNotFound => "E0025",
+            Code::IncludeDepthExceeded => "E0026",
+            Code::ErrorDirective => "E0027",
+            Code::WarningDirective => "E0028",
+            Code::InvalidLineDirective => "E0029",
+        }
+    }
+}
+
+/// A secondary location which gives more context to a diagnostic.
+#[deriving(Clone,PartialEq,Show)]
+pub struct Label
+{
+    pub span: Span,
+    pub message: String,
+}
+
+/// A problem found while reading source.
+#[deriving(Clone,PartialEq)]
+pub struct Diagnostic
+{
+    pub code: Code,
+    pub severity: Severity,
+    pub message: String,
+
+    /// The location the diagnostic is about.
+    /// This is missing when there is nothing sensible to point at, such as the end of input.
+    pub span: Option<Span>,
+    pub labels: Vec<Label>,
+    pub notes: Vec<String>,
+}
+
+impl Diagnostic
+{
+    /// Creates a new diagnostic.
+    pub fn new(severity: Severity, code: Code, message: String) -> Diagnostic
+    {
+        Diagnostic {
+            code: code,
+            severity: severity,
+            message: message,
+
+            span: None,
+            labels: Vec::new(),
+            notes: Vec::new(),
+        }
+    }
+
+    /// Creates a new error.
+    pub fn error(code: Code, message: String) -> Diagnostic
+    {
+        Diagnostic::new(Severity::Error, code, message)
+    }
+
+    /// Creates a new warning.
+    pub fn warning(code: Code, message: String) -> Diagnostic
+    {
+        Diagnostic::new(Severity::Warning, code, message)
+    }
+
+    /// Creates a new error which stops any further processing.
+    pub fn fatal(code: Code, message: String) -> Diagnostic
+    {
+        Diagnostic::new(Severity::Fatal, code, message)
+    }
+
+    /// Sets the primary location of the diagnostic.
+    pub fn with_span(mut self, span: Span) -> Diagnostic
+    {
+        self.span = Some(span);
+        self
+    }
+
+    /// Sets the primary location of the diagnostic, unless it already has one.
+    pub fn or_span(mut self, span: Span) -> Diagnostic
+    {
+        if self.span.is_none() {
+            self.span = Some(span);
+        }
+        self
+    }
+
+    /// Adds a secondary location.
+    pub fn with_label(mut self, span: Span, message: String) -> Diagnostic
+    {
+        self.labels.push(Label {
+            span: span,
+            message: message,
+        });
+        self
+    }
+
+    /// Adds a note.
+    pub fn with_note(mut self, note: String) -> Diagnostic
+    {
+        self.notes.push(note);
+        self
+    }
+
+    /// Checks if the diagnostic stops the source from being accepted.
+    pub fn is_error(&self) -> bool
+    {
+        self.severity >= Severity::Error
+    }
+}
+
+impl std::fmt::Show for Diagnostic
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(),std::fmt::Error>
+    {
+        match self.span {
+            Some(ref span) => { try!(write!(formatter, "{}: ", span)); },
+            None => (),
+        }
+
+        try!(write!(formatter, "{}[{}]: {}", self.severity, self.code.id(), self.message));
+
+        for label in self.labels.iter() {
+            try!(write!(formatter, "\n  {}: {}", label.span, label.message));
+        }
+
+        for note in self.notes.iter() {
+            try!(write!(formatter, "\n  note: {}", note));
+        }
+
+        Ok(())
+    }
+}
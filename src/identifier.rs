@@ -1,20 +1,52 @@
 
+use token::literal;
+use standard::Standard;
+use diagnostic::{Diagnostic,Code};
+use util::IteratorPeeker;
+
 /// An identifier.
-#[deriving(Clone,Show)]
+#[deriving(Clone,PartialEq,Eq,Hash,Show)]
 pub struct Identifier
 {
+    /// The name, with any universal character names replaced by the characters they stand for.
     pub name: String,
 }
 
 impl Identifier
 {
     /// Creates a new identifier from a name.
-    /// Returns None if name is an invalid identifier.
-    pub fn from_name(name: String) -> Option<Identifier>
+    /// Returns an error if name is an invalid identifier.
+    pub fn from_name(name: String, standard: Standard) -> Result<Identifier,Diagnostic>
     {
-        // TODO: validate identifier.
-        Some(Identifier {
-            name: name,
+        let mut decoded = String::new();
+        let mut it = IteratorPeeker::new(name.as_slice().chars());
+
+        loop {
+            let c = match it.next() {
+                Some('\\') => try!(decode_universal_character(&mut it)),
+                Some(c) => c,
+                None => break,
+            };
+
+            let valid = match decoded.is_empty() {
+                true => is_valid_first_char(c, standard.clone()),
+                false => is_valid_char(c, standard.clone()),
+            };
+
+            if !valid {
+                return Err(Diagnostic::error(Code::InvalidIdentifier,
+                                             format!("'{}' is not a valid identifier", name)));
+            }
+
+            decoded.push(c);
+        }
+
+        if decoded.is_empty() {
+            return Err(Diagnostic::error(Code::InvalidIdentifier, "identifiers can't be empty".to_string()));
+        }
+
+        Ok(Identifier {
+            name: decoded,
         })
     }
 }
@@ -22,19 +54,87 @@ impl Identifier
 
 
 /// Checks whether a character is a valid first character for an identifier.
-pub fn is_valid_first_char(c: char) -> bool
+pub fn is_valid_first_char(c: char, standard: Standard) -> bool
 {
     match c {
-        '_' => true,
-        _ => return c.is_alphabetic(),
+        'a'...'z' | 'A'...'Z' | '_' => true,
+        c if (c as u32) < 0x80 => false,
+        c => match standard {
+            Standard::C23 => c.is_XID_start(),
+            _ => is_annex_d_char(c) && !is_annex_d_combining_char(c),
+        },
     }
 }
 
 /// Checks whether a character is allowed to exist inside an identifier (not including first character).
-pub fn is_valid_char(c: char) -> bool
+pub fn is_valid_char(c: char, standard: Standard) -> bool
 {
     match c {
-        '_' => true,
-        _ => return c.is_alphanumeric(),
+        '0'...'9' => true,
+        c if (c as u32) < 0x80 => is_valid_first_char(c, standard),
+        c => match standard {
+            Standard::C23 => c.is_XID_continue(),
+            _ => is_annex_d_char(c),
+        },
+    }
+}
+
+/// Reads a universal character name, just after the backslash.
+fn decode_universal_character<I: Iterator<char>>(it: &mut IteratorPeeker<char, I>) -> Result<char,Diagnostic>
+{
+    let digit_count = match it.next() {
+        Some('u') => 4u,
+        Some('U') => 8u,
+        _ => {
+            return Err(Diagnostic::error(Code::InvalidIdentifier,
+                                         "a backslash in an identifier must start a universal character name".to_string()));
+        },
+    };
+
+    let mut value: u32 = 0;
+
+    for _ in range(0, digit_count) {
+        match it.next() {
+            Some(d) if d.is_digit(16) => {
+                value = (value * 16) + (d.to_digit(16).unwrap() as u32);
+            },
+            _ => {
+                return Err(Diagnostic::error(Code::InvalidUniversalCharacter,
+                                             format!("incomplete universal character name, expected {} hex digits", digit_count)));
+            },
+        }
+    }
+
+    literal::universal_character(value)
+}
+
+/// Checks whether a character is in one of the ranges of C11 Annex D.1,
+/// which lists the extended characters allowed in identifiers.
+fn is_annex_d_char(c: char) -> bool
+{
+    match c as u32 {
+        0x00A8 | 0x00AA | 0x00AD | 0x00AF |
+        0x00B2...0x00B5 | 0x00B7...0x00BA | 0x00BC...0x00BE |
+        0x00C0...0x00D6 | 0x00D8...0x00F6 | 0x00F8...0x00FF |
+        0x0100...0x167F | 0x1681...0x180D | 0x180F...0x1FFF |
+        0x200B...0x200D | 0x202A...0x202E | 0x203F...0x2040 | 0x2054 | 0x2060...0x206F |
+        0x2070...0x218F | 0x2460...0x24FF | 0x2776...0x2793 | 0x2C00...0x2DFF | 0x2E80...0x2FFF |
+        0x3004...0x3007 | 0x3021...0x302F | 0x3031...0x303F |
+        0x3040...0xD7FF |
+        0xF900...0xFD3D | 0xFD40...0xFDCF | 0xFDF0...0xFE44 | 0xFE47...0xFFFD => true,
+
+        // everything in the supplementary planes, other than the last two code points of each.
+        v if v >= 0x10000 && v <= 0xEFFFD => (v & 0xFFFF) <= 0xFFFD,
+        _ => false,
+    }
+}
+
+/// Checks whether a character is in one of the ranges of C11 Annex D.2,
+/// which can't start an identifier.
+fn is_annex_d_combining_char(c: char) -> bool
+{
+    match c as u32 {
+        0x0300...0x036F | 0x1DC0...0x1DFF | 0x20D0...0x20FF | 0xFE20...0xFE2F => true,
+        _ => false,
     }
 }
@@ -0,0 +1,119 @@
+
+use std;
+
+/// Identifies a source file.
+#[deriving(Clone,PartialEq,Eq,Hash,Show)]
+pub struct FileId(pub uint);
+
+/// A position inside a source file.
+#[deriving(Clone,PartialEq,Eq)]
+pub struct Position
+{
+    /// The byte offset from the start of the file.
+    pub offset: uint,
+    /// The line number, starting from 1.
+    pub line: uint,
+    /// The column number, starting from 1.
+    pub column: uint,
+}
+
+impl Position
+{
+    /// Gets the position of the first character in a file.
+    pub fn start() -> Position
+    {
+        Position {
+            offset: 0,
+            line: 1,
+            column: 1,
+        }
+    }
+    
+    /// Moves the position past a character.
+    pub fn advance(&mut self, c: char)
+    {
+        self.offset += c.len_utf8();
+        
+        match c {
+            '\n' => {
+                self.line += 1;
+                self.column = 1;
+            },
+            _ => {
+                self.column += 1;
+            },
+        }
+    }
+}
+
+impl std::fmt::Show for Position
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(),std::fmt::Error>
+    {
+        write!(formatter, "{}:{}", self.line, self.column)
+    }
+}
+
+/// A region of a source file.
+#[deriving(Clone,PartialEq,Eq)]
+pub struct Span
+{
+    pub file: FileId,
+    /// The position of the first character.
+    pub start: Position,
+    /// The position just past the last character.
+    pub end: Position,
+}
+
+impl Span
+{
+    /// Creates a new span.
+    pub fn new(file: FileId, start: Position, end: Position) -> Span
+    {
+        Span {
+            file: file,
+            start: start,
+            end: end,
+        }
+    }
+
+    /// Creates a span which does not point at any real source.
+    /// This is used for tokens which are built by hand.
+    pub fn dummy() -> Span
+    {
+        Span::new(FileId(0), Position::start(), Position::start())
+    }
+
+    /// Creates a span which covers both this span and another.
+    pub fn to(&self, other: &Span) -> Span
+    {
+        Span::new(self.file.clone(), self.start.clone(), other.end.clone())
+    }
+}
+
+impl std::fmt::Show for Span
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(),std::fmt::Error>
+    {
+        self.start.fmt(formatter)
+    }
+}
+
+/// A value along with the region of source it came from.
+#[deriving(Clone,Show)]
+pub struct Spanned<T>
+{
+    pub node: T,
+    pub span: Span,
+}
+
+impl<T> Spanned<T>
+{
+    pub fn new(node: T, span: Span) -> Spanned<T>
+    {
+        Spanned {
+            node: node,
+            span: span,
+        }
+    }
+}
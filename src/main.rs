@@ -1,4 +1,10 @@
 
+extern crate arena;
+extern crate time;
+
+#[cfg(test)]
+extern crate test;
+
 pub use self::identifier::Identifier;
 
 pub mod ast;
@@ -7,6 +13,13 @@ pub mod token;
 pub mod util;
 pub mod preprocessor;
 pub mod identifier;
+pub mod location;
+pub mod standard;
+pub mod diagnostic;
+pub mod source;
+
+#[cfg(test)]
+mod bench;
 
 
 
@@ -23,13 +36,13 @@ fn main()
 #[allow(dead_code)]
 fn test_tokenizer(text: &'static str)
 {
-    let tk1 = token::Tokenizer::new(text.chars());
+    let tk1 = token::Tokenizer::new(text);
     
     test_tokenizer_it(tk1);
 }
 
 #[allow(dead_code)]
-fn test_tokenizer_it<I: Iterator<char>>(mut tk: token::Tokenizer<I>)
+fn test_tokenizer_it(tk: token::Tokenizer)
 {
     for token in tk {
         println!("{}", token);
@@ -38,12 +51,12 @@ fn test_tokenizer_it<I: Iterator<char>>(mut tk: token::Tokenizer<I>)
 
 fn test_preprocessor(text: &'static str)
 {
-    let tokenizer = token::Tokenizer::new(text.chars());
+    let tokenizer = token::Tokenizer::new(text);
     
     test_preprocessor_tk(tokenizer);
 }
 
-fn test_preprocessor_tk<I: Iterator<char>>(tk: token::Tokenizer<I>)
+fn test_preprocessor_tk(tk: token::Tokenizer)
 {
     let mut p = preprocessor::Preprocessor::new(tk);
 
@@ -55,7 +68,7 @@ fn test_preprocessor_tk<I: Iterator<char>>(tk: token::Tokenizer<I>)
 #[allow(dead_code)]
 fn test_parser(text: &'static str)
 {
-    let tk = token::Tokenizer::new(text.chars());
+    let tk = token::Tokenizer::new(text);
     let mut parser = parser::Parser::new();
     
     match parser.parse(tk) {
@@ -6,6 +6,8 @@ use token::expect;
 use Identifier;
 use token::{Tokenizer,Token};
 use ast::{Expr,Expression,Statement};
+use location::Spanned;
+use diagnostic::{Diagnostic,Code};
 
 /// A parser can read C code and encode it into an AST.
 pub struct Parser
@@ -24,16 +26,14 @@ impl Parser
     }
     
     /// Parses a tokenizer.
-    pub fn parse<I: Iterator<char>>(&mut self, mut it: Tokenizer<I>) -> Result<(), String>
+    pub fn parse<'a>(&mut self, mut it: Tokenizer<'a>) -> Result<(), Diagnostic>
     {
         loop {
             match it.peek() {
-                Some(Ok(tok)) => match tok {
-                    Token(token::Kind::NewLine,_) => {
-                        it.eat();
-                        continue;
-                    },
-                    _ => { return Err(format!("unknown token: {}", tok)); } // we don't know how to handle this token.
+                // we don't know how to handle this token.
+                Some(Ok(tok)) => {
+                    return Err(Diagnostic::error(Code::UnexpectedToken, format!("unknown token '{}'", tok))
+                                   .with_span(tok.span.clone()));
                 },
                 Some(Err(err)) => { return Err(err); },
                 None => { return Ok(()); }, // we reached the end.
@@ -42,46 +42,50 @@ impl Parser
     }
     
     /// Parses an expression.
-    fn parse_expression<I: Iterator<char>>(&mut self, it: &mut Tokenizer<I>) -> Result<ast::Expr, String>
+    fn parse_expression<'a>(&mut self, it: &mut Tokenizer<'a>) -> Result<Spanned<ast::Expr>, Diagnostic>
     {
         match try!(expect::something(it.peek()))
         {
-            Token(token::Kind::Word, _) => self.parse_identifier(it),
-            Token(token::Kind::IntegerLiteral, _) => self.parse_integer_literal(it),
-            _ => Err("unexpected token".to_string())
+            Token { kind: token::Kind::Word, .. } => self.parse_identifier(it),
+            Token { kind: token::Kind::PpNumber, .. } => self.parse_integer_literal(it),
+            Token { kind: token::Kind::IntegerLiteral(..), .. } => self.parse_integer_literal(it),
+            tok => Err(Diagnostic::error(Code::UnexpectedToken, format!("expected an expression, found '{}'", tok))
+                           .with_span(tok.span.clone()))
         }
     }
     
-    fn parse_identifier<I: Iterator<char>>(&mut self, it: &mut Tokenizer<I>) -> Result<ast::Expr, String>
+    fn parse_identifier<'a>(&mut self, it: &mut Tokenizer<'a>) -> Result<Spanned<ast::Expr>, Diagnostic>
     {
         match expect::assert_kind(it.next(), token::Kind::Word) {
             // create a new identifier.
-            Token(token::Kind::Word, name) => match Identifier::from_name(name) {
+            Token { kind: token::Kind::Word, value: name, span } => match Identifier::from_name(name, it.standard()) {
                 // the word is a valid identifier.
-                Some(ident) => Ok(ident.to_expr()),
+                Ok(ident) => Ok(Spanned::new(ident.to_expr(), span)),
                 
                 // the word is an ill formed identifier.
-                None => Err("invalid identifier".to_string()),
+                Err(diagnostic) => Err(diagnostic.with_span(span)),
             },
             _ => unreachable!(),
         }
     }
     
-    fn parse_integer_literal<I: Iterator<char>>(&mut self, it: &mut Tokenizer<I>) -> Result<ast::Expr, String>
+    fn parse_integer_literal<'a>(&mut self, it: &mut Tokenizer<'a>) -> Result<Spanned<ast::Expr>, Diagnostic>
     {
-        match expect::assert_kind(it.next(), token::Kind::IntegerLiteral) {
+        // numbers are only checked once preprocessing is done.
+        match try!(expect::assert_something(it.next()).classify()) {
             // create a new integer literal.
-            Token(token::Kind::IntegerLiteral, val) => {
-                Ok(ast::expressions::IntegerLiteral(val).to_expr())
+            Token { kind: token::Kind::IntegerLiteral(..), value: val, span } => {
+                Ok(Spanned::new(ast::expressions::IntegerLiteral(val).to_expr(), span))
             },
-            _ => unreachable!(),
+            tok => Err(Diagnostic::error(Code::UnexpectedToken, format!("expected an integer, found '{}'", tok))
+                           .with_span(tok.span.clone())),
         }
     }
     
     
     /// Parses an argument list (a set of expressions, in parentheses, seperated by commas).
     /// For example: "(abc, 123, bvs)".
-    fn parse_argument_list<I: Iterator<char>>(&mut self, it: &mut Tokenizer<I>) -> Result<Vec<ast::Expr>, String>
+    fn parse_argument_list<'a>(&mut self, it: &mut Tokenizer<'a>) -> Result<Vec<Spanned<ast::Expr>>, Diagnostic>
     {
         expect::assert_token(it.next(), &Token::left_parenthesis());
         
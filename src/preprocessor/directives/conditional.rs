@@ -0,0 +1,62 @@
+
+/// Specifies which conditional directive it is.
+#[deriving(Clone,PartialEq,Show)]
+pub enum Kind
+{
+    If,
+    Ifdef,
+    Ifndef,
+    Elif,
+    Elifdef,
+    Elifndef,
+    Else,
+    Endif,
+}
+
+impl Kind
+{
+    /// Gets the conditional directive with a name.
+    pub fn from_name(name: &str) -> Option<Kind>
+    {
+        match name {
+            "if" => Some(Kind::If),
+            "ifdef" => Some(Kind::Ifdef),
+            "ifndef" => Some(Kind::Ifndef),
+            "elif" => Some(Kind::Elif),
+            "elifdef" => Some(Kind::Elifdef),
+            "elifndef" => Some(Kind::Elifndef),
+            "else" => Some(Kind::Else),
+            "endif" => Some(Kind::Endif),
+            _ => None,
+        }
+    }
+
+    /// Checks whether the directive opens a new group.
+    pub fn is_opening(&self) -> bool
+    {
+        match *self {
+            Kind::If | Kind::Ifdef | Kind::Ifndef => true,
+            _ => false,
+        }
+    }
+}
+
+/// A preprocessor conditional directive, such as `#ifdef` or `#endif`.
+#[deriving(Clone,Show)]
+pub struct Conditional
+{
+    pub kind: Kind,
+    /// Whether the source after the directive is included.
+    pub taken: bool,
+}
+
+impl Conditional
+{
+    pub fn new(kind: Kind, taken: bool) -> Conditional
+    {
+        Conditional {
+            kind: kind,
+            taken: taken,
+        }
+    }
+}
@@ -3,17 +3,20 @@ use Identifier;
 use token::Token;
 
 /// Specifies the kind of a define.
-#[deriving(Show)]
+#[deriving(Clone,PartialEq,Show)]
 pub enum Kind
 {
     Constant,
     Function {
         params: Vec<Identifier>,
+        /// Whether the last parameter takes any number of arguments.
+        /// It is named `__VA_ARGS__` if it was written as '...'.
+        variadic: bool,
     },
 }
 
 /// A preprocessor `#define~ block.
-#[deriving(Show)]
+#[deriving(Clone,Show)]
 pub struct Define
 {
     pub name: Identifier,
@@ -34,7 +37,7 @@ impl Define
         }
     }
     
-    pub fn function(name: Identifier, params: Vec<Identifier>, body: Option<Vec<Token>>) -> Define
+    pub fn function(name: Identifier, params: Vec<Identifier>, variadic: bool, body: Option<Vec<Token>>) -> Define
     {
         Define {
             name: name,
@@ -42,7 +45,28 @@ impl Define
             
             kind: Kind::Function {
                 params: params,
+                variadic: variadic,
             },
         }
     }
+    
+    /// Checks whether two definitions are the same, meaning that a macro may be
+    /// redefined from one to the other.
+    /// The bodies must have the same tokens, with whitespace in the same places.
+    pub fn is_equivalent(&self, other: &Define) -> bool
+    {
+        if (self.name != other.name) || (self.kind != other.kind) {
+            return false;
+        }
+        
+        match (&self.body, &other.body) {
+            (&Some(ref a), &Some(ref b)) => {
+                (a.len() == b.len()) && a.iter().zip(b.iter()).enumerate().all(|(i, (x, y))| {
+                    (x == y) && ((i == 0) || (x.leading_space == y.leading_space))
+                })
+            },
+            (&None, &None) => true,
+            _ => false,
+        }
+    }
 }
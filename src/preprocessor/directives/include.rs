@@ -0,0 +1,18 @@
+
+use token::HeaderDelimiter;
+
+/// A preprocessor `#include` or `#include_next` directive.
+#[deriving(Clone,Show)]
+pub struct Include
+{
+    /// The name of the header, without its delimiters.
+    pub name: String,
+    pub delimiter: HeaderDelimiter,
+    /// Whether it is `#include_next`, which carries on searching after the directory the current file was found in.
+    pub next: bool,
+    
+    /// The path the header was found at.
+    pub path: Path,
+    /// Whether the header wasn't read again, because of an include guard or `#pragma once`.
+    pub skipped: bool,
+}
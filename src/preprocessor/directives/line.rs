@@ -0,0 +1,12 @@
+
+/// A preprocessor `#line` directive, which changes the line number and file name
+/// reported for the source after it.
+#[deriving(Clone,Show)]
+pub struct Line
+{
+    /// The number of the next line.
+    pub line: uint,
+    /// The name the file is reported as from now on, if it was given.
+    /// This is what `__FILE__` gives; spans and diagnostics still name the file which was read.
+    pub file: Option<String>,
+}
@@ -1,11 +1,32 @@
 
+use Identifier;
+
 pub use self::define::Define;
+pub use self::conditional::Conditional;
+pub use self::include::Include;
+pub use self::line::Line;
+pub use self::pragma::Pragma;
 
 pub mod define;
+pub mod conditional;
+pub mod include;
+pub mod line;
+pub mod pragma;
 
 /// A preprocessor directive.
 #[deriving(Show)]
 pub enum Directive
 {
     Define(Define),
+    Undef(Identifier),
+    Conditional(Conditional),
+    Include(Include),
+    Line(Line),
+    Pragma(Pragma),
+    /// An `#error`, along with its message.
+    Error(String),
+    /// A `#warning`, along with its message.
+    Warning(String),
+    /// A `#` on its own, which does nothing.
+    Null,
 }
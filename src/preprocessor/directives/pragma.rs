@@ -0,0 +1,35 @@
+
+use token::Token;
+
+/// A preprocessor `#pragma` directive or `_Pragma` operator.
+/// The tokens are kept as they are, for whatever understands them.
+#[deriving(Clone,Show)]
+pub struct Pragma
+{
+    pub tokens: Vec<Token>,
+}
+
+impl Pragma
+{
+    pub fn new(tokens: Vec<Token>) -> Pragma
+    {
+        Pragma {
+            tokens: tokens,
+        }
+    }
+    
+    /// Gets the first word of the pragma, such as `once` or `GCC`.
+    pub fn name(&self) -> Option<&str>
+    {
+        match self.tokens.first() {
+            Some(token) if token.is_identifier() => Some(token.value.as_slice()),
+            _ => None,
+        }
+    }
+    
+    /// Checks whether it is `#pragma once`.
+    pub fn is_once(&self) -> bool
+    {
+        (self.tokens.len() == 1) && (self.name() == Some("once"))
+    }
+}
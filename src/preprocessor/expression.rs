@@ -0,0 +1,572 @@
+
+//! Evaluation of the constant expressions in '#if' and '#elif' directives.
+//!
+//! Every signed integer acts like `intmax_t` and every unsigned one like
+//! `uintmax_t`, which are both taken to be 64 bits wide.
+
+use std;
+use std::num::Int;
+
+use Identifier;
+use token;
+use token::{Token,Punctuator};
+use token::numeric;
+use token::literal::Encoding;
+use standard::Standard;
+use preprocessor::macros;
+use preprocessor::macros::PpToken;
+use location::Span;
+use diagnostic::{Diagnostic,Code};
+
+/// The value of a preprocessor expression.
+#[deriving(Clone,PartialEq,Show)]
+pub enum Value
+{
+    Signed(i64),
+    Unsigned(u64),
+}
+
+impl Value
+{
+    /// Gets the `int` which a condition gives.
+    fn from_bool(value: bool) -> Value
+    {
+        match value {
+            true => Value::Signed(1),
+            false => Value::Signed(0),
+        }
+    }
+
+    pub fn is_true(&self) -> bool
+    {
+        match *self {
+            Value::Signed(value) => value != 0,
+            Value::Unsigned(value) => value != 0,
+        }
+    }
+
+    fn is_unsigned(&self) -> bool
+    {
+        match *self {
+            Value::Signed(..) => false,
+            Value::Unsigned(..) => true,
+        }
+    }
+
+    /// Gets the bits of the value.
+    fn bits(&self) -> u64
+    {
+        match *self {
+            Value::Signed(value) => value as u64,
+            Value::Unsigned(value) => value,
+        }
+    }
+}
+
+/// Evaluates the expression of an '#if' or '#elif' directive.
+/// `defined` is worked out before macros are expanded, and any identifiers
+/// left over afterwards are taken to be 0.
+/// `span` is the span of the directive, which is used if the expression ends too soon.
+pub fn evaluate(table: &macros::Table, standard: Standard, tokens: Vec<Token>, span: &Span) -> Result<bool,Diagnostic>
+{
+    let tokens = try!(replace_defined(table, standard.clone(), tokens));
+    let tokens = try!(macros::expand_all(table, standard.clone(), tokens));
+
+    let mut evaluator = Evaluator {
+        tokens: tokens.into_iter().map(|pp_token| pp_token.token).collect(),
+        pos: 0,
+        standard: standard,
+        span: span.clone(),
+    };
+
+    let value = try!(evaluator.expression(0, true));
+
+    match evaluator.tokens.get(evaluator.pos) {
+        Some(token) => Err(unexpected(token, "expected the end of the expression")),
+        None => Ok(value.is_true()),
+    }
+}
+
+/// Replaces each `defined X` and `defined(X)` with 1 or 0.
+fn replace_defined(table: &macros::Table, standard: Standard, tokens: Vec<Token>) -> Result<Vec<PpToken>,Diagnostic>
+{
+    let mut result = Vec::new();
+    let mut it = tokens.into_iter();
+
+    loop {
+        let token = match it.next() {
+            Some(token) => token,
+            None => break,
+        };
+
+        if !token.is_identifier() || (token.value.as_slice() != "defined") {
+            result.push(PpToken::new(token));
+            continue;
+        }
+
+        let mut name = it.next();
+        let parenthesized = name.as_ref().map(|name| name.is_punctuator(Punctuator::LeftParenthesis)).unwrap_or(false);
+
+        if parenthesized {
+            name = it.next();
+        }
+
+        let name = match name {
+            Some(ref name) if name.is_identifier() => name.clone(),
+            Some(ref other) => { return Err(unexpected(other, "expected a macro name after 'defined'")); },
+            None => {
+                return Err(Diagnostic::error(Code::UnexpectedToken,
+                                             "expected a macro name after 'defined'".to_string())
+                               .with_span(token.span.clone()));
+            },
+        };
+
+        if parenthesized {
+            match it.next() {
+                Some(ref right) if right.is_punctuator(Punctuator::RightParenthesis) => (),
+                Some(ref other) => { return Err(unexpected(other, "expected ')'")); },
+                None => {
+                    return Err(Diagnostic::error(Code::UnexpectedToken, "expected ')'".to_string())
+                                   .with_span(name.span.clone()));
+                },
+            }
+        }
+
+        let identifier = match Identifier::from_name(name.value.clone(), standard.clone()) {
+            Ok(identifier) => identifier,
+            Err(diagnostic) => { return Err(diagnostic.with_span(name.span.clone())); },
+        };
+
+        let value = match table.is_defined(&identifier) {
+            true => "1",
+            false => "0",
+        };
+
+        let mut replacement = Token::new(token::Kind::PpNumber, value.to_string(), token.span.to(&name.span));
+        replacement.leading_space = token.leading_space;
+
+        result.push(PpToken::new(replacement));
+    }
+
+    Ok(result)
+}
+
+/// Gets the precedence of a binary operator, or nothing if a token isn't one.
+/// The conditional operator is included, as it is parsed in the same way.
+fn precedence(token: &Token) -> Option<uint>
+{
+    let punctuator = match token.kind {
+        token::Kind::Punctuator(ref punctuator) => punctuator.clone(),
+        _ => { return None; },
+    };
+
+    match punctuator {
+        Punctuator::Comma => Some(1),
+        Punctuator::Question => Some(2),
+        Punctuator::PipePipe => Some(3),
+        Punctuator::AmpersandAmpersand => Some(4),
+        Punctuator::Pipe => Some(5),
+        Punctuator::Caret => Some(6),
+        Punctuator::Ampersand => Some(7),
+        Punctuator::EqualEqual | Punctuator::NotEqual => Some(8),
+        Punctuator::Less | Punctuator::Greater | Punctuator::LessEqual | Punctuator::GreaterEqual => Some(9),
+        Punctuator::LeftShift | Punctuator::RightShift => Some(10),
+        Punctuator::Plus | Punctuator::Minus => Some(11),
+        Punctuator::Asterix | Punctuator::ForwardSlash | Punctuator::Percent => Some(12),
+        _ => None,
+    }
+}
+
+/// Creates an error for a token which doesn't belong.
+fn unexpected(token: &Token, message: &str) -> Diagnostic
+{
+    Diagnostic::error(Code::UnexpectedToken, format!("{}, found '{}'", message, token.value))
+        .with_span(token.span.clone())
+}
+
+/// A precedence climbing parser, which works out the value of an expression as it goes.
+/// Operands which aren't evaluated, such as the right of '0 && x', are still parsed,
+/// but can't cause errors such as division by zero.
+struct Evaluator
+{
+    tokens: Vec<Token>,
+    pos: uint,
+    standard: Standard,
+
+    /// The span of the directive.
+    span: Span,
+}
+
+impl Evaluator
+{
+    /// Parses an expression whose binary operators have at least a given precedence.
+    fn expression(&mut self, min_precedence: uint, evaluated: bool) -> Result<Value,Diagnostic>
+    {
+        let mut lhs = try!(self.unary(evaluated));
+
+        loop {
+            let (op, precedence) = match self.tokens.get(self.pos) {
+                Some(token) => match precedence(token) {
+                    Some(precedence) if precedence >= min_precedence => (token.clone(), precedence),
+                    _ => break,
+                },
+                None => break,
+            };
+
+            self.pos += 1;
+
+            let punctuator = match op.kind {
+                token::Kind::Punctuator(punctuator) => punctuator,
+                _ => unreachable!(),
+            };
+
+            lhs = match punctuator {
+                Punctuator::AmpersandAmpersand => {
+                    let rhs = try!(self.expression(precedence + 1, evaluated && lhs.is_true()));
+                    Value::from_bool(lhs.is_true() && rhs.is_true())
+                },
+                Punctuator::PipePipe => {
+                    let rhs = try!(self.expression(precedence + 1, evaluated && !lhs.is_true()));
+                    Value::from_bool(lhs.is_true() || rhs.is_true())
+                },
+                Punctuator::Question => {
+                    // the middle operand is parsed as if it were parenthesized.
+                    let if_true = try!(self.expression(0, evaluated && lhs.is_true()));
+                    try!(self.expect(Punctuator::Colon, "expected ':'"));
+
+                    // the conditional operator groups right to left.
+                    let if_false = try!(self.expression(precedence, evaluated && !lhs.is_true()));
+
+                    let value = match lhs.is_true() {
+                        true => if_true.clone(),
+                        false => if_false.clone(),
+                    };
+
+                    // the result has the type of both operands, after the usual arithmetic conversions.
+                    match if_true.is_unsigned() || if_false.is_unsigned() {
+                        true => Value::Unsigned(value.bits()),
+                        false => value,
+                    }
+                },
+                Punctuator::Comma => try!(self.expression(precedence + 1, evaluated)),
+                punctuator => {
+                    let rhs = try!(self.expression(precedence + 1, evaluated));
+
+                    match binary(punctuator, lhs, rhs, evaluated) {
+                        Ok(value) => value,
+                        Err(message) => {
+                            return Err(Diagnostic::error(Code::InvalidConstantExpression, message)
+                                           .with_span(op.span.clone()));
+                        },
+                    }
+                },
+            };
+        }
+
+        Ok(lhs)
+    }
+
+    /// Parses a unary expression.
+    fn unary(&mut self, evaluated: bool) -> Result<Value,Diagnostic>
+    {
+        let token = match self.tokens.get(self.pos) {
+            Some(token) => token.clone(),
+            None => {
+                return Err(Diagnostic::error(Code::UnexpectedToken, "expected an expression".to_string())
+                               .with_span(self.span.clone()));
+            },
+        };
+
+        self.pos += 1;
+
+        match token.kind {
+            token::Kind::Punctuator(Punctuator::Plus) => {
+                self.unary(evaluated)
+            },
+            token::Kind::Punctuator(Punctuator::Minus) => {
+                match try!(self.unary(evaluated)) {
+                    Value::Signed(std::i64::MIN) if evaluated => {
+                        Err(Diagnostic::error(Code::InvalidConstantExpression, "integer overflow in negation".to_string())
+                                .with_span(token.span.clone()))
+                    },
+                    Value::Signed(value) => Ok(Value::Signed(value.wrapping_neg())),
+                    Value::Unsigned(value) => Ok(Value::Unsigned(value.wrapping_neg())),
+                }
+            },
+            token::Kind::Punctuator(Punctuator::Tilde) => {
+                match try!(self.unary(evaluated)) {
+                    Value::Signed(value) => Ok(Value::Signed(!value)),
+                    Value::Unsigned(value) => Ok(Value::Unsigned(!value)),
+                }
+            },
+            token::Kind::Punctuator(Punctuator::Exclamation) => {
+                let value = try!(self.unary(evaluated));
+                Ok(Value::from_bool(!value.is_true()))
+            },
+            token::Kind::Punctuator(Punctuator::LeftParenthesis) => {
+                let value = try!(self.expression(0, evaluated));
+                try!(self.expect(Punctuator::RightParenthesis, "expected ')'"));
+
+                Ok(value)
+            },
+            token::Kind::PpNumber => self.number(&token),
+            token::Kind::CharLiteral(ref literal) => Ok(character(literal)),
+            _ if token.is_identifier() => {
+                // 'true' and 'false' are keywords in C23, and any other identifier is 0.
+                match (token.value.as_slice(), self.standard >= Standard::C23) {
+                    ("true", true) => Ok(Value::Signed(1)),
+                    _ => Ok(Value::Signed(0)),
+                }
+            },
+            _ => Err(unexpected(&token, "expected an expression")),
+        }
+    }
+
+    /// Works out the value of an integer constant.
+    fn number(&self, token: &Token) -> Result<Value,Diagnostic>
+    {
+        let integer = match numeric::parse(token.value.as_slice()) {
+            Ok(token::Kind::IntegerLiteral(integer)) => integer,
+            Ok(..) => {
+                return Err(Diagnostic::error(Code::InvalidConstantExpression,
+                                             "floating constants are not allowed in preprocessor expressions".to_string())
+                               .with_span(token.span.clone()));
+            },
+            Err(diagnostic) => { return Err(diagnostic.with_span(token.span.clone())); },
+        };
+
+        // a constant too big for intmax_t is unsigned.
+        match integer.unsigned || (integer.value > (std::i64::MAX as u64)) {
+            true => Ok(Value::Unsigned(integer.value)),
+            false => Ok(Value::Signed(integer.value as i64)),
+        }
+    }
+
+    /// Reads a punctuator which has to be next.
+    fn expect(&mut self, punctuator: Punctuator, message: &str) -> Result<(),Diagnostic>
+    {
+        match self.tokens.get(self.pos) {
+            Some(token) if token.is_punctuator(punctuator.clone()) => (),
+            Some(token) => { return Err(unexpected(token, message)); },
+            None => {
+                return Err(Diagnostic::error(Code::UnexpectedToken, message.to_string())
+                               .with_span(self.span.clone()));
+            },
+        }
+
+        self.pos += 1;
+        Ok(())
+    }
+}
+
+/// Works out the value of a character constant.
+/// A plain `char` is signed, and each extra character of a multi-character
+/// constant shifts the value along by a byte, as in GCC.
+fn character(literal: &token::literal::Literal) -> Value
+{
+    match literal.encoding {
+        Encoding::Plain => {
+            let units = literal.code_units.as_slice();
+
+            match units.len() {
+                1 => Value::Signed((units[0] as u8) as i8 as i64),
+                _ => Value::Signed(units.iter().fold(0i64, |value, &unit| (value << 8) | ((unit & 0xFF) as i64)) as i32 as i64),
+            }
+        },
+        // 'char8_t', 'char16_t' and 'char32_t' are unsigned, while 'wchar_t' is signed.
+        Encoding::Utf8 | Encoding::Utf16 | Encoding::Utf32 => {
+            Value::Unsigned(literal.code_units.last().map(|&unit| unit as u64).unwrap_or(0))
+        },
+        Encoding::Wide => {
+            Value::Signed(literal.code_units.last().map(|&unit| unit as i32 as i64).unwrap_or(0))
+        },
+    }
+}
+
+/// Applies a binary operator.
+/// Gives back a message if the result is undefined, unless the operands aren't evaluated.
+fn binary(op: Punctuator, lhs: Value, rhs: Value, evaluated: bool) -> Result<Value,String>
+{
+    // shifts have the type of their left operand, and the amount has to be in range.
+    if (op == Punctuator::LeftShift) || (op == Punctuator::RightShift) {
+        let amount = match rhs {
+            Value::Signed(amount) if (amount >= 0) && (amount < 64) => amount as uint,
+            Value::Unsigned(amount) if amount < 64 => amount as uint,
+            _ if !evaluated => 0,
+            _ => { return Err("shift amount is out of range".to_string()); },
+        };
+
+        return Ok(match (op, lhs) {
+            (Punctuator::LeftShift, Value::Signed(value)) => Value::Signed(((value as u64) << amount) as i64),
+            (Punctuator::LeftShift, Value::Unsigned(value)) => Value::Unsigned(value << amount),
+            (_, Value::Signed(value)) => Value::Signed(value >> amount),
+            (_, Value::Unsigned(value)) => Value::Unsigned(value >> amount),
+        });
+    }
+
+    let is_division = (op == Punctuator::ForwardSlash) || (op == Punctuator::Percent);
+
+    if is_division && !rhs.is_true() {
+        return match evaluated {
+            true => Err("division by zero".to_string()),
+            false => Ok(Value::Signed(0)),
+        };
+    }
+
+    // the usual arithmetic conversions.
+    if lhs.is_unsigned() || rhs.is_unsigned() {
+        let (a, b) = (lhs.bits(), rhs.bits());
+
+        return Ok(match op {
+            // unsigned arithmetic wraps around.
+            Punctuator::Asterix => Value::Unsigned(a.wrapping_mul(b)),
+            Punctuator::ForwardSlash => Value::Unsigned(a / b),
+            Punctuator::Percent => Value::Unsigned(a % b),
+            Punctuator::Plus => Value::Unsigned(a.wrapping_add(b)),
+            Punctuator::Minus => Value::Unsigned(a.wrapping_sub(b)),
+            Punctuator::Less => Value::from_bool(a < b),
+            Punctuator::Greater => Value::from_bool(a > b),
+            Punctuator::LessEqual => Value::from_bool(a <= b),
+            Punctuator::GreaterEqual => Value::from_bool(a >= b),
+            Punctuator::EqualEqual => Value::from_bool(a == b),
+            Punctuator::NotEqual => Value::from_bool(a != b),
+            Punctuator::Ampersand => Value::Unsigned(a & b),
+            Punctuator::Caret => Value::Unsigned(a ^ b),
+            Punctuator::Pipe => Value::Unsigned(a | b),
+            _ => unreachable!(),
+        });
+    }
+
+    let (a, b) = (lhs.bits() as i64, rhs.bits() as i64);
+
+    // the one signed division which overflows.
+    if is_division && (a == std::i64::MIN) && (b == -1) {
+        return match evaluated {
+            true => Err("integer overflow in division".to_string()),
+            false => Ok(Value::Signed(0)),
+        };
+    }
+
+    // signed arithmetic which overflows is undefined.
+    let arithmetic = match op {
+        Punctuator::Asterix => Some(a.checked_mul(b)),
+        Punctuator::Plus => Some(a.checked_add(b)),
+        Punctuator::Minus => Some(a.checked_sub(b)),
+        _ => None,
+    };
+
+    match arithmetic {
+        Some(Some(value)) => { return Ok(Value::Signed(value)); },
+        Some(None) => {
+            return match evaluated {
+                true => Err("integer overflow".to_string()),
+                false => Ok(Value::Signed(0)),
+            };
+        },
+        None => (),
+    }
+
+    Ok(match op {
+        Punctuator::ForwardSlash => Value::Signed(a / b),
+        Punctuator::Percent => Value::Signed(a % b),
+        Punctuator::Less => Value::from_bool(a < b),
+        Punctuator::Greater => Value::from_bool(a > b),
+        Punctuator::LessEqual => Value::from_bool(a <= b),
+        Punctuator::GreaterEqual => Value::from_bool(a >= b),
+        Punctuator::EqualEqual => Value::from_bool(a == b),
+        Punctuator::NotEqual => Value::from_bool(a != b),
+        Punctuator::Ampersand => Value::Signed(a & b),
+        Punctuator::Caret => Value::Signed(a ^ b),
+        Punctuator::Pipe => Value::Signed(a | b),
+        _ => unreachable!(),
+    })
+}
+
+#[cfg(test)]
+mod tests
+{
+    use Identifier;
+    use token::Tokenizer;
+    use standard::Standard;
+    use preprocessor::macros;
+    use preprocessor::directives::Define;
+    use location::Span;
+    use diagnostic::{Diagnostic,Code};
+
+    /// Evaluates an expression, with `X` defined as a macro.
+    fn eval(source: &str) -> Result<bool,Diagnostic>
+    {
+        let mut table = macros::Table::new();
+        table.define(Define::constant(Identifier { name: "X".to_string() }, None));
+
+        let tokens = Tokenizer::new(source).map(|token| token.unwrap()).collect();
+        super::evaluate(&table, Standard::default(), tokens, &Span::dummy())
+    }
+
+    fn assert_error(source: &str)
+    {
+        match eval(source) {
+            Err(diagnostic) => assert_eq!(diagnostic.code, Code::InvalidConstantExpression),
+            Ok(value) => panic!("'{}' gave {} rather than an error", source, value),
+        }
+    }
+
+    #[test]
+    fn precedence()
+    {
+        assert_eq!(eval("1 + 2 * 3 == 7"), Ok(true));
+        assert_eq!(eval("(1 + 2) * 3 == 9"), Ok(true));
+        assert_eq!(eval("10 - 4 - 3 == 3"), Ok(true));
+        assert_eq!(eval("1 || 0 && 0"), Ok(true));
+        assert_eq!(eval("1 << 2 + 1 == 8"), Ok(true));
+        assert_eq!(eval("0 ? 1 : 2 == 2"), Ok(true));
+        assert_eq!(eval("1 ? 0 : 0 ? 1 : 1"), Ok(false));
+    }
+
+    #[test]
+    fn unsigned_promotion()
+    {
+        assert_eq!(eval("-1 < 0"), Ok(true));
+        assert_eq!(eval("-1 < 0u"), Ok(false));
+        assert_eq!(eval("-1 == 0xffffffffffffffff"), Ok(true));
+        assert_eq!(eval("0u - 1 == 0xffffffffffffffff"), Ok(true));
+        assert_eq!(eval("0xffffffffffffffff + 1 == 0"), Ok(true));
+        assert_eq!(eval("(1 ? -1 : 0u) > 0"), Ok(true));
+    }
+
+    #[test]
+    fn defined()
+    {
+        assert_eq!(eval("defined X && defined(X)"), Ok(true));
+        assert_eq!(eval("defined Y || defined(Y)"), Ok(false));
+        assert_eq!(eval("Y == 0"), Ok(true));
+        assert!(eval("defined").is_err());
+        assert!(eval("defined(X").is_err());
+    }
+
+    #[test]
+    fn division()
+    {
+        assert_eq!(eval("7 / 2 == 3 && 7 % 2 == 1"), Ok(true));
+        assert_error("1 / 0");
+        assert_error("1 % 0u");
+        assert_error("(-0x7fffffffffffffff - 1) / -1");
+
+        // operands which aren't evaluated can't go wrong.
+        assert_eq!(eval("0 && 1 / 0"), Ok(false));
+        assert_eq!(eval("1 || 1 / 0"), Ok(true));
+        assert_eq!(eval("1 ? 1 : 1 / 0"), Ok(true));
+    }
+
+    #[test]
+    fn overflow()
+    {
+        assert_error("0x7fffffffffffffff + 1");
+        assert_error("-0x7fffffffffffffff - 2");
+        assert_error("0x7fffffffffffffff * 2");
+        assert_error("-(-0x7fffffffffffffff - 1)");
+
+        assert_eq!(eval("0 && 0x7fffffffffffffff + 1"), Ok(false));
+        assert_eq!(eval("-0x7fffffffffffffff - 1 < 0"), Ok(true));
+    }
+}
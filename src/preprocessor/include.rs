@@ -0,0 +1,105 @@
+
+//! Finding and reading the files named by '#include' directives.
+
+use std::cell::RefCell;
+use std::io::File;
+use std::io::fs::PathExtensions;
+
+use arena::TypedArena;
+
+use source::{Source,Fallback};
+use token::HeaderDelimiter;
+use location::FileId;
+use diagnostic::{Diagnostic,Code};
+
+/// The source files which have been read for '#include' directives.
+/// They are kept outside of the preprocessor, as the tokenizers reading them borrow their text.
+pub struct Files
+{
+    sources: TypedArena<Source>,
+    /// The path of each file, where the file with id `n` is at index `n - 1`.
+    /// `FileId(0)` is left for the main source.
+    paths: RefCell<Vec<Path>>,
+}
+
+impl Files
+{
+    pub fn new() -> Files
+    {
+        Files {
+            sources: TypedArena::new(),
+            paths: RefCell::new(Vec::new()),
+        }
+    }
+
+    /// Reads a file, giving it a new id.
+    pub fn read<'a>(&'a self, path: &Path, fallback: Fallback) -> Result<(&'a Source, FileId),Diagnostic>
+    {
+        let mut file = match File::open(path) {
+            Ok(file) => file,
+            Err(error) => {
+                return Err(Diagnostic::fatal(Code::ReadError, format!("couldn't open '{}': {}", path.display(), error)));
+            },
+        };
+
+        let source = match Source::read_to_end(&mut file, fallback) {
+            Ok(source) => source,
+            Err(diagnostic) => { return Err(diagnostic.with_note(format!("while reading '{}'", path.display()))); },
+        };
+
+        let mut paths = self.paths.borrow_mut();
+        paths.push(path.clone());
+
+        Ok((self.sources.alloc(source), FileId(paths.len())))
+    }
+
+    /// Gets the path of a file which has been read.
+    pub fn path(&self, file: &FileId) -> Option<Path>
+    {
+        let FileId(index) = *file;
+
+        match index {
+            0 => None,
+            _ => self.paths.borrow().get(index - 1).map(|path| path.clone()),
+        }
+    }
+}
+
+/// Looks for a header.
+/// A quoted name is looked for in `current` first, which is the directory of the including file.
+/// After that, the search paths are tried in order, starting from `start` so that
+/// '#include_next' can carry on from where the including file was found.
+/// Gives back the path of the header, along with the index of the search path it was found in.
+pub fn find(name: &str, delimiter: HeaderDelimiter, current: Option<&Path>, search_paths: &[Path], start: uint)
+    -> Option<(Path, Option<uint>)>
+{
+    let relative = Path::new(name);
+
+    if relative.is_absolute() {
+        return match relative.is_file() {
+            true => Some((relative, None)),
+            false => None,
+        };
+    }
+
+    match (delimiter, current) {
+        (HeaderDelimiter::Quotes, Some(dir)) => {
+            let path = dir.join(name);
+
+            if path.is_file() {
+                return Some((path, None));
+            }
+        },
+        _ => (),
+    }
+
+    for (index, dir) in search_paths.iter().enumerate().skip(start) {
+        let path = dir.join(name);
+
+        if path.is_file() {
+            return Some((path, Some(index)));
+        }
+    }
+
+    None
+}
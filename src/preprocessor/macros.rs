@@ -0,0 +1,1016 @@
+
+use std::cell::Cell;
+use std::collections::HashMap;
+
+use Identifier;
+use token;
+use token::{Token,Tokenizer,Punctuator};
+use token::tokenizer::Options;
+use standard::Standard;
+use preprocessor::directives::{define,Define};
+use diagnostic::{Diagnostic,Code};
+
+/// The names of the macros a token was produced by.
+/// A token is never expanded by a macro in its hide-set, which stops
+/// a macro from expanding itself forever.
+#[deriving(Clone,PartialEq,Show)]
+pub struct HideSet
+{
+    names: Vec<Identifier>,
+}
+
+impl HideSet
+{
+    /// Creates an empty hide-set.
+    pub fn new() -> HideSet
+    {
+        HideSet {
+            names: Vec::new(),
+        }
+    }
+
+    /// Checks whether a name is in the hide-set.
+    pub fn contains(&self, name: &Identifier) -> bool
+    {
+        self.names.contains(name)
+    }
+
+    /// Gets a copy of the hide-set with a name added.
+    pub fn with(&self, name: &Identifier) -> HideSet
+    {
+        let mut result = self.clone();
+
+        if !result.contains(name) {
+            result.names.push(name.clone());
+        }
+
+        result
+    }
+
+    /// Gets the names in either hide-set.
+    pub fn union(&self, other: &HideSet) -> HideSet
+    {
+        let mut result = self.clone();
+
+        for name in other.names.iter() {
+            if !result.contains(name) {
+                result.names.push(name.clone());
+            }
+        }
+
+        result
+    }
+
+    /// Gets the names in both hide-sets.
+    pub fn intersection(&self, other: &HideSet) -> HideSet
+    {
+        HideSet {
+            names: self.names.iter().filter(|name| other.contains(*name)).map(|name| name.clone()).collect(),
+        }
+    }
+}
+
+/// A token being preprocessed, along with its hide-set.
+#[deriving(Clone,Show)]
+pub struct PpToken
+{
+    pub token: Token,
+    pub hide_set: HideSet,
+}
+
+impl PpToken
+{
+    /// Creates a token which didn't come from any macro.
+    pub fn new(token: Token) -> PpToken
+    {
+        PpToken {
+            token: token,
+            hide_set: HideSet::new(),
+        }
+    }
+}
+
+/// A macro whose replacement depends on where it is used.
+#[deriving(Clone,PartialEq,Show)]
+pub enum Builtin
+{
+    /// `__FILE__`, the name of the current file.
+    File,
+    /// `__LINE__`, the current line number.
+    Line,
+    /// `__COUNTER__`, which counts up from 0 each time it is used.
+    Counter,
+}
+
+/// The macros which are currently defined.
+pub struct Table
+{
+    macros: HashMap<Identifier, Define>,
+    builtins: HashMap<Identifier, Builtin>,
+
+    /// The name given by `__FILE__`.
+    file: String,
+    /// The number given by `__LINE__`.
+    line: uint,
+    /// The number given by the next `__COUNTER__`.
+    counter: Cell<uint>,
+}
+
+impl Table
+{
+    /// Creates an empty table.
+    pub fn new() -> Table
+    {
+        Table {
+            macros: HashMap::new(),
+            builtins: HashMap::new(),
+
+            file: String::new(),
+            line: 1,
+            counter: Cell::new(0),
+        }
+    }
+
+    /// Defines a macro.
+    /// Gives back the definition it replaced, if there was one.
+    pub fn define(&mut self, define: Define) -> Option<Define>
+    {
+        self.builtins.remove(&define.name);
+        self.macros.insert(define.name.clone(), define)
+    }
+
+    /// Defines a builtin macro.
+    pub fn define_builtin(&mut self, name: Identifier, builtin: Builtin)
+    {
+        self.macros.remove(&name);
+        self.builtins.insert(name, builtin);
+    }
+
+    /// Removes the definition of a macro.
+    /// Gives back the definition, if there was one and it wasn't a builtin.
+    pub fn undefine(&mut self, name: &Identifier) -> Option<Define>
+    {
+        self.builtins.remove(name);
+        self.macros.remove(name)
+    }
+
+    /// Sets the file name given by `__FILE__`.
+    pub fn set_file(&mut self, file: String)
+    {
+        self.file = file;
+    }
+
+    /// Sets the line number given by `__LINE__`.
+    pub fn set_line(&mut self, line: uint)
+    {
+        self.line = line;
+    }
+
+    /// Gets the definition of a macro.
+    pub fn get(&self, name: &Identifier) -> Option<&Define>
+    {
+        self.macros.get(name)
+    }
+
+    /// Checks whether a macro is defined.
+    pub fn is_defined(&self, name: &Identifier) -> bool
+    {
+        self.macros.contains_key(name) || self.builtins.contains_key(name)
+    }
+
+    /// Gets the replacement of a builtin macro, which is always a single token.
+    fn expand_builtin(&self, builtin: &Builtin, name: &Token, standard: Standard) -> Token
+    {
+        let mut token = match *builtin {
+            Builtin::File => {
+                let mut spelling = "\"".to_string();
+
+                for c in self.file.as_slice().chars() {
+                    match c {
+                        '"' | '\\' => { spelling.push('\\'); spelling.push(c); },
+                        '\n' => spelling.push_str("\\n"),
+                        c => spelling.push(c),
+                    }
+                }
+
+                spelling.push('"');
+
+                match lex_one(spelling.as_slice(), standard) {
+                    Some(token) => token,
+                    None => unreachable!(),
+                }
+            },
+            Builtin::Line => Token::new(token::Kind::PpNumber, self.line.to_string(), name.span.clone()),
+            Builtin::Counter => {
+                let counter = self.counter.get();
+                self.counter.set(counter + 1);
+
+                Token::new(token::Kind::PpNumber, counter.to_string(), name.span.clone())
+            },
+        };
+
+        token.span = name.span.clone();
+        token.leading_space = name.leading_space;
+        token
+    }
+}
+
+/// Somewhere tokens are read from while macros are expanded.
+pub trait Input
+{
+    /// Reads the next token.
+    fn next_token(&mut self) -> Option<Result<PpToken,Diagnostic>>;
+
+    /// Peeks at the next token.
+    fn peek_token(&mut self) -> Option<Result<PpToken,Diagnostic>>;
+
+    /// Puts tokens back, to be read before anything else.
+    fn push_tokens(&mut self, tokens: Vec<PpToken>);
+}
+
+/// A list of tokens can be used as input, as long as it is kept in reverse
+/// order so that the next token is at the end.
+impl Input for Vec<PpToken>
+{
+    fn next_token(&mut self) -> Option<Result<PpToken,Diagnostic>>
+    {
+        self.pop().map(|pp_token| Ok(pp_token))
+    }
+
+    fn peek_token(&mut self) -> Option<Result<PpToken,Diagnostic>>
+    {
+        self.last().map(|pp_token| Ok(pp_token.clone()))
+    }
+
+    fn push_tokens(&mut self, tokens: Vec<PpToken>)
+    {
+        self.extend(tokens.into_iter().rev());
+    }
+}
+
+/// Gets the name of the macro a token may refer to.
+fn macro_name(token: &Token, standard: Standard) -> Option<Identifier>
+{
+    match token.is_identifier() {
+        true => Identifier::from_name(token.value.clone(), standard).ok(),
+        false => None,
+    }
+}
+
+/// Expands a token if it names a macro, reading any arguments from the input.
+/// Gives the token back if it isn't expanded. Otherwise, the replacement is
+/// pushed back onto the input so that it is rescanned.
+pub fn expand<I: Input>(table: &Table, standard: Standard, pp_token: PpToken, input: &mut I)
+    -> Result<Option<PpToken>,Diagnostic>
+{
+    let name = match macro_name(&pp_token.token, standard.clone()) {
+        Some(name) => name,
+        None => { return Ok(Some(pp_token)); },
+    };
+
+    // a macro is never expanded inside its own replacement.
+    if pp_token.hide_set.contains(&name) {
+        return Ok(Some(pp_token));
+    }
+
+    match table.builtins.get(&name) {
+        Some(builtin) => {
+            let token = table.expand_builtin(builtin, &pp_token.token, standard);
+
+            return Ok(Some(PpToken {
+                token: token,
+                hide_set: pp_token.hide_set,
+            }));
+        },
+        None => (),
+    }
+
+    let define = match table.get(&name) {
+        Some(define) => define,
+        None => { return Ok(Some(pp_token)); },
+    };
+
+    let mut replacement: Vec<PpToken> = match define.kind {
+        define::Kind::Constant => {
+            let hide_set = pp_token.hide_set.with(&name);
+
+            try!(substitute(table, standard, define, Vec::new(), &hide_set))
+        },
+        define::Kind::Function { ref params, variadic } => {
+            // the name of a function-like macro on its own is left alone.
+            match input.peek_token() {
+                Some(Ok(ref next)) if next.token.is_punctuator(Punctuator::LeftParenthesis) => (),
+                Some(Err(diagnostic)) => { return Err(diagnostic); },
+                _ => { return Ok(Some(pp_token)); },
+            }
+
+            input.next_token(); // skip '('.
+
+            // commas don't split the variadic arguments.
+            let split_limit = match variadic {
+                true => Some(params.len()),
+                false => None,
+            };
+
+            let (mut args, right_parenthesis) = try!(read_arguments(input, &pp_token.token, split_limit));
+
+            // a macro with no parameters is invoked with one empty argument.
+            if (params.len() == 0) && (args.len() == 1) && args[0].is_empty() {
+                args.clear();
+            }
+
+            // the variadic arguments may be left out altogether.
+            if variadic && (args.len() + 1 == params.len()) {
+                args.push(Vec::new());
+            }
+
+            if args.len() != params.len() {
+                let expected = match variadic {
+                    true => format!("at least {}", params.len() - 1),
+                    false => format!("{}", params.len()),
+                };
+
+                return Err(Diagnostic::error(Code::MacroArgumentCount,
+                                             format!("macro '{}' takes {} arguments, but {} were given",
+                                                     name.name, expected, args.len()))
+                               .with_span(pp_token.token.span.to(&right_parenthesis.token.span)));
+            }
+
+            // the replacement is hidden from anything hidden from both the name and the
+            // closing parenthesis, as well as from the macro itself.
+            let hide_set = pp_token.hide_set.intersection(&right_parenthesis.hide_set).with(&name);
+
+            try!(substitute(table, standard, define, args, &hide_set))
+        },
+    };
+
+    // the replacement takes the place of the name, including the space before it.
+    match replacement.iter_mut().next() {
+        Some(first) => { first.token.leading_space = pp_token.token.leading_space; },
+        None => (),
+    }
+
+    input.push_tokens(replacement);
+    Ok(None)
+}
+
+/// Expands every macro in a list of tokens, as if they were the whole input.
+pub fn expand_all(table: &Table, standard: Standard, tokens: Vec<PpToken>) -> Result<Vec<PpToken>,Diagnostic>
+{
+    let mut input: Vec<PpToken> = tokens.into_iter().rev().collect();
+    let mut result = Vec::new();
+
+    loop {
+        let pp_token = match input.pop() {
+            Some(pp_token) => pp_token,
+            None => break,
+        };
+
+        match try!(expand(table, standard.clone(), pp_token, &mut input)) {
+            Some(pp_token) => result.push(pp_token),
+            None => (),
+        }
+    }
+
+    Ok(result)
+}
+
+/// Reads the arguments of a function-like macro, just after the '('.
+/// Arguments may cover several lines, and commas inside nested parentheses don't split them.
+/// Once there are `split_limit` arguments, commas don't split them either.
+/// Gives back the arguments, along with the closing parenthesis.
+fn read_arguments<I: Input>(input: &mut I, name: &Token, split_limit: Option<uint>)
+    -> Result<(Vec<Vec<PpToken>>, PpToken),Diagnostic>
+{
+    let mut args = Vec::new();
+    let mut current = Vec::new();
+    let mut depth = 0u;
+
+    loop {
+        let pp_token = match input.next_token() {
+            Some(Ok(pp_token)) => pp_token,
+            Some(Err(diagnostic)) => { return Err(diagnostic); },
+            None => {
+                return Err(Diagnostic::error(Code::UnterminatedMacroInvocation,
+                                             format!("unterminated argument list invoking macro '{}'", name.value))
+                               .with_span(name.span.clone()));
+            },
+        };
+
+        match pp_token.token.kind {
+            token::Kind::Punctuator(Punctuator::LeftParenthesis) => {
+                depth += 1;
+            },
+            token::Kind::Punctuator(Punctuator::RightParenthesis) if depth == 0 => {
+                args.push(current);
+                return Ok((args, pp_token));
+            },
+            token::Kind::Punctuator(Punctuator::RightParenthesis) => {
+                depth -= 1;
+            },
+            token::Kind::Punctuator(Punctuator::Comma) if (depth == 0) &&
+                                                          split_limit.map(|limit| args.len() + 1 < limit).unwrap_or(true) => {
+                args.push(current);
+                current = Vec::new();
+                continue;
+            },
+            _ => (),
+        }
+
+        current.push(pp_token);
+    }
+}
+
+/// Replaces the parameters in the body of a macro with their arguments, and
+/// carries out the '#' and '##' operators.
+/// Arguments are fully macro-expanded first, unless they are an operand of '#' or '##'.
+fn substitute(table: &Table, standard: Standard, define: &Define, args: Vec<Vec<PpToken>>, hide_set: &HideSet)
+    -> Result<Vec<PpToken>,Diagnostic>
+{
+    let body = match define.body {
+        Some(ref body) => body.as_slice(),
+        None => { return Ok(Vec::new()); },
+    };
+
+    // object-like macros have no parameters, and '#' is just a token in them.
+    let (params, is_function, variadic) = match define.kind {
+        define::Kind::Constant => ([].as_slice(), false, false),
+        define::Kind::Function { ref params, variadic } => (params.as_slice(), true, variadic),
+    };
+
+    let substitution = Substitution {
+        table: table,
+        standard: standard,
+        body: body,
+        params: params,
+        args: args,
+        is_function: is_function,
+        variadic: variadic,
+    };
+
+    let result = try!(substitution.substitute(0, body.len()));
+
+    Ok(result.into_iter().map(|pp_token| {
+        PpToken {
+            hide_set: pp_token.hide_set.union(hide_set),
+            token: pp_token.token,
+        }
+    }).collect())
+}
+
+/// The arguments of a macro invocation, along with the body they are substituted into.
+struct Substitution<'a>
+{
+    table: &'a Table,
+    standard: Standard,
+    body: &'a [Token],
+    params: &'a [Identifier],
+    args: Vec<Vec<PpToken>>,
+
+    is_function: bool,
+    /// Whether the last parameter is variadic.
+    variadic: bool,
+}
+
+impl<'a> Substitution<'a>
+{
+    /// Substitutes the body tokens in the range `start..end`.
+    fn substitute(&self, start: uint, end: uint) -> Result<Vec<PpToken>,Diagnostic>
+    {
+        let mut result: Vec<PpToken> = Vec::new();
+        // whether the last item was empty, which '##' treats as a placemarker.
+        let mut placemarker = false;
+        let mut i = start;
+
+        while i < end {
+            if self.body[i].is_punctuator(Punctuator::HashHash) {
+                let is_comma_elision = (i > start) && self.is_punctuator_at(i - 1, Punctuator::Comma) &&
+                                       self.is_variadic_param_at(i + 1) && !placemarker;
+
+                let (rhs, length) = try!(self.item_at(i + 1, end));
+                i += 1 + length;
+
+                if is_comma_elision {
+                    // GNU: ', ## __VA_ARGS__' drops the comma when there are no variadic
+                    // arguments, and otherwise leaves it alone rather than pasting.
+                    placemarker = rhs.is_empty();
+
+                    match placemarker {
+                        true => { result.pop(); },
+                        false => result.extend(rhs.into_iter()),
+                    }
+                } else if placemarker {
+                    // pasting onto a placemarker gives the other side.
+                    placemarker = rhs.is_empty();
+                    result.extend(rhs.into_iter());
+                } else if !rhs.is_empty() {
+                    let mut rhs = rhs.into_iter();
+
+                    // with nothing before the '##', there is nothing to paste onto.
+                    match result.pop() {
+                        Some(lhs) => {
+                            let pasted = try!(paste(lhs, rhs.next().unwrap(), self.standard.clone()));
+                            result.push(pasted);
+                        },
+                        None => (),
+                    }
+
+                    result.extend(rhs);
+                }
+
+                continue;
+            }
+
+            let (mut item, length) = try!(self.item_at(i, end));
+
+            // arguments are expanded, unless they are pasted.
+            if (length == 1) && self.param_at(i).is_some() && !self.is_punctuator_at(i + 1, Punctuator::HashHash) {
+                item = try!(expand_all(self.table, self.standard.clone(), item));
+            }
+
+            // the item takes the place of the parameter, including the space before it.
+            match item.iter_mut().next() {
+                Some(first) => { first.token.leading_space = self.body[i].leading_space; },
+                None => (),
+            }
+
+            placemarker = item.is_empty();
+            result.extend(item.into_iter());
+            i += length;
+        }
+
+        Ok(result)
+    }
+
+    /// Reads the item of the body at an index, without expanding it.
+    /// This is a token, an argument, a stringified argument or a `__VA_OPT__`.
+    /// Gives back the tokens along with the number of body tokens used, which is
+    /// none at `end` or past it.
+    fn item_at(&self, i: uint, end: uint) -> Result<(Vec<PpToken>, uint),Diagnostic>
+    {
+        if i >= end {
+            return Ok((Vec::new(), 0));
+        }
+
+        if self.is_function && self.is_punctuator_at(i, Punctuator::Hash) {
+            match self.param_at(i + 1) {
+                Some(index) => {
+                    let string = try!(stringify(self.args[index].as_slice(), &self.body[i], self.standard.clone()));
+                    return Ok((vec![PpToken::new(string)], 2));
+                },
+                None => (),
+            }
+
+            if self.is_va_opt_at(i + 1) {
+                let (content, length) = try!(self.item_at(i + 1, end));
+                let string = try!(stringify(content.as_slice(), &self.body[i], self.standard.clone()));
+                return Ok((vec![PpToken::new(string)], 1 + length));
+            }
+        }
+
+        if self.is_va_opt_at(i) {
+            let end = self.va_opt_end(i);
+
+            // the contents are only used if there are variadic arguments.
+            let content = match self.args.last().map(|arg| arg.is_empty()).unwrap_or(true) {
+                true => Vec::new(),
+                false => try!(self.substitute(i + 2, end)),
+            };
+
+            return Ok((content, end + 1 - i));
+        }
+
+        match self.param_at(i) {
+            Some(index) => Ok((self.args[index].clone(), 1)),
+            None => Ok((vec![PpToken::new(self.body[i].clone())], 1)),
+        }
+    }
+
+    /// Gets the index of the parameter at an index of the body.
+    fn param_at(&self, i: uint) -> Option<uint>
+    {
+        self.body.get(i)
+            .and_then(|token| macro_name(token, self.standard.clone()))
+            .and_then(|name| self.params.iter().position(|param| param == &name))
+    }
+
+    /// Checks whether the variadic parameter is at an index of the body.
+    fn is_variadic_param_at(&self, i: uint) -> bool
+    {
+        self.variadic && (self.param_at(i) == Some(self.params.len() - 1))
+    }
+
+    fn is_punctuator_at(&self, i: uint, punctuator: Punctuator) -> bool
+    {
+        self.body.get(i).map(|token| token.is_punctuator(punctuator)).unwrap_or(false)
+    }
+
+    /// Checks whether a `__VA_OPT__(...)` starts at an index of the body.
+    fn is_va_opt_at(&self, i: uint) -> bool
+    {
+        self.variadic && self.body.get(i).map(|token| is_va_opt(token)).unwrap_or(false) &&
+            self.is_punctuator_at(i + 1, Punctuator::LeftParenthesis)
+    }
+
+    /// Gets the index of the ')' closing the `__VA_OPT__` at an index of the body.
+    fn va_opt_end(&self, i: uint) -> uint
+    {
+        closing_parenthesis(self.body, i + 1).unwrap_or(self.body.len())
+    }
+}
+
+/// Checks whether a token is `__VA_OPT__`.
+fn is_va_opt(token: &Token) -> bool
+{
+    token.is_identifier() && (token.value.as_slice() == "__VA_OPT__")
+}
+
+/// Gets the index of the ')' which closes the '(' at an index.
+fn closing_parenthesis(tokens: &[Token], open: uint) -> Option<uint>
+{
+    let mut depth = 0u;
+
+    for (i, token) in tokens.iter().enumerate().skip(open) {
+        if token.is_punctuator(Punctuator::LeftParenthesis) {
+            depth += 1;
+        } else if token.is_punctuator(Punctuator::RightParenthesis) {
+            depth -= 1;
+
+            if depth == 0 {
+                return Some(i);
+            }
+        }
+    }
+
+    None
+}
+
+/// Turns the tokens of an argument into a string literal, for the '#' operator.
+/// Tokens are separated by a single space wherever there was whitespace between them,
+/// and any '"' or '\' in string literals and character constants is escaped.
+fn stringify(arg: &[PpToken], hash: &Token, standard: Standard) -> Result<Token,Diagnostic>
+{
+    let mut spelling = "\"".to_string();
+
+    for (i, pp_token) in arg.iter().enumerate() {
+        if (i > 0) && pp_token.token.leading_space {
+            spelling.push(' ');
+        }
+
+        match pp_token.token.kind {
+            token::Kind::StringLiteral(..) | token::Kind::CharLiteral(..) => {
+                for c in pp_token.token.value.as_slice().chars() {
+                    if (c == '"') || (c == '\\') {
+                        spelling.push('\\');
+                    }
+
+                    spelling.push(c);
+                }
+            },
+            _ => spelling.push_str(pp_token.token.value.as_slice()),
+        }
+    }
+
+    spelling.push('"');
+
+    match lex_one(spelling.as_slice(), standard) {
+        Some(mut token) => {
+            token.span = hash.span.clone();
+            Ok(token)
+        },
+        None => Err(Diagnostic::error(Code::InvalidMacroOperator,
+                                      format!("stringifying the argument gives {}, which is not a valid string literal", spelling))
+                        .with_span(hash.span.clone())),
+    }
+}
+
+/// Pastes two tokens together, for the '##' operator.
+fn paste(lhs: PpToken, rhs: PpToken, standard: Standard) -> Result<PpToken,Diagnostic>
+{
+    let spelling = format!("{}{}", lhs.token.value, rhs.token.value);
+
+    match lex_one(spelling.as_slice(), standard) {
+        Some(mut token) => {
+            token.span = lhs.token.span.to(&rhs.token.span);
+            token.leading_space = lhs.token.leading_space;
+
+            Ok(PpToken {
+                token: token,
+                hide_set: lhs.hide_set.intersection(&rhs.hide_set),
+            })
+        },
+        None => Err(Diagnostic::error(Code::InvalidMacroOperator,
+                                      format!("pasting '{}' and '{}' does not give a valid token",
+                                              lhs.token.value, rhs.token.value))
+                        .with_span(lhs.token.span.to(&rhs.token.span))),
+    }
+}
+
+/// Reads a spelling as a token, giving nothing unless it is exactly one valid token.
+fn lex_one(spelling: &str, standard: Standard) -> Option<Token>
+{
+    let options = Options {
+        standard: standard,
+        .. Options::new()
+    };
+
+    let mut tokenizer = Tokenizer::with_options(spelling, options);
+
+    match (tokenizer.next(), tokenizer.next()) {
+        (Some(Ok(token)), None) => Some(token),
+        _ => None,
+    }
+}
+
+/// Checks the '#' and '##' operators in the body of a macro, and any use of `__VA_ARGS__` or `__VA_OPT__`.
+/// '##' can't start or end the body or the contents of a `__VA_OPT__`, and in a function-like macro,
+/// '#' must be followed by a parameter.
+/// `__VA_ARGS__` is only allowed in a macro with a '...' parameter, and `__VA_OPT__` in any variadic macro.
+pub fn check_body(define: &Define, standard: Standard) -> Result<(),Diagnostic>
+{
+    let body = match define.body {
+        Some(ref body) => body.as_slice(),
+        None => { return Ok(()); },
+    };
+
+    for token in [body.first(), body.last()].iter().filter_map(|token| *token) {
+        if token.is_punctuator(Punctuator::HashHash) {
+            return Err(Diagnostic::error(Code::InvalidMacroOperator,
+                                         "'##' cannot appear at either end of a macro body".to_string())
+                           .with_span(token.span.clone()));
+        }
+    }
+
+    let (params, is_function, variadic) = match define.kind {
+        define::Kind::Constant => ([].as_slice(), false, false),
+        define::Kind::Function { ref params, variadic } => (params.as_slice(), true, variadic),
+    };
+
+    let has_va_args = variadic && params.last().map(|param| param.name.as_slice() == "__VA_ARGS__").unwrap_or(false);
+
+    for (i, token) in body.iter().enumerate() {
+        if token.is_identifier() && (token.value.as_slice() == "__VA_ARGS__") && !has_va_args {
+            return Err(Diagnostic::error(Code::InvalidVariadicUse,
+                                         "'__VA_ARGS__' can only be used in a macro with a '...' parameter".to_string())
+                           .with_span(token.span.clone()));
+        }
+
+        if is_va_opt(token) {
+            if !variadic {
+                return Err(Diagnostic::error(Code::InvalidVariadicUse,
+                                             "'__VA_OPT__' can only be used in a variadic macro".to_string())
+                               .with_span(token.span.clone()));
+            }
+
+            let end = match body.get(i + 1).map(|next| next.is_punctuator(Punctuator::LeftParenthesis)) {
+                Some(true) => closing_parenthesis(body, i + 1),
+                _ => None,
+            };
+
+            let end = match end {
+                Some(end) => end,
+                None => {
+                    return Err(Diagnostic::error(Code::InvalidVariadicUse,
+                                                 "'__VA_OPT__' must be followed by a parenthesized list of tokens".to_string())
+                                   .with_span(token.span.clone()));
+                },
+            };
+
+            let content = body.slice(i + 2, end);
+
+            for edge in [content.first(), content.last()].iter().filter_map(|edge| *edge) {
+                if edge.is_punctuator(Punctuator::HashHash) {
+                    return Err(Diagnostic::error(Code::InvalidMacroOperator,
+                                                 "'##' cannot appear at either end of the contents of '__VA_OPT__'".to_string())
+                                   .with_span(edge.span.clone()));
+                }
+            }
+        }
+    }
+
+    // '#' is just a token in object-like macros.
+    if !is_function {
+        return Ok(());
+    }
+
+    for (i, token) in body.iter().enumerate() {
+        if !token.is_punctuator(Punctuator::Hash) {
+            continue;
+        }
+
+        let is_param = body.get(i + 1)
+            .map(|next| {
+                macro_name(next, standard.clone()).map(|name| params.contains(&name)).unwrap_or(false) ||
+                    (variadic && is_va_opt(next))
+            })
+            .unwrap_or(false);
+
+        if !is_param {
+            return Err(Diagnostic::error(Code::InvalidMacroOperator,
+                                         "'#' is not followed by a macro parameter".to_string())
+                           .with_span(token.span.clone()));
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests
+{
+    use Identifier;
+    use token::{Token,Tokenizer};
+    use standard::Standard;
+    use preprocessor::directives::Define;
+    use diagnostic::{Diagnostic,Code};
+    use super::{Table,PpToken,expand_all};
+
+    fn identifier(name: &str) -> Identifier
+    {
+        Identifier { name: name.to_string() }
+    }
+
+    fn lex(source: &str) -> Vec<Token>
+    {
+        Tokenizer::new(source).map(|token| token.unwrap()).collect()
+    }
+
+    fn object(table: &mut Table, name: &str, body: &str)
+    {
+        table.define(Define::constant(identifier(name), Some(lex(body))));
+    }
+
+    fn function(table: &mut Table, name: &str, params: &[&str], body: &str)
+    {
+        let params = params.iter().map(|param| identifier(*param)).collect();
+        table.define(Define::function(identifier(name), params, false, Some(lex(body))));
+    }
+
+    /// Expands the macros in a source, giving back the spelling of each token separated by spaces.
+    fn expand(table: &Table, source: &str) -> Result<String,Diagnostic>
+    {
+        let tokens = lex(source).into_iter().map(|token| PpToken::new(token)).collect();
+        let expanded = try!(expand_all(table, Standard::default(), tokens));
+
+        let spellings: Vec<String> = expanded.into_iter().map(|pp_token| pp_token.token.value).collect();
+        Ok(spellings.connect(" "))
+    }
+
+    #[test]
+    fn object_like()
+    {
+        let mut table = Table::new();
+        object(&mut table, "A", "1 + B");
+        object(&mut table, "B", "2");
+        object(&mut table, "EMPTY", "");
+
+        assert_eq!(expand(&table, "A * A"), Ok("1 + 2 * 1 + 2".to_string()));
+        assert_eq!(expand(&table, "a EMPTY b"), Ok("a b".to_string()));
+        assert_eq!(expand(&table, "C"), Ok("C".to_string()));
+    }
+
+    #[test]
+    fn recursive_object_like()
+    {
+        let mut table = Table::new();
+        object(&mut table, "X", "X + 1");
+        object(&mut table, "P", "Q");
+        object(&mut table, "Q", "P");
+        object(&mut table, "R", "[R S]");
+        object(&mut table, "S", "(R)");
+
+        // a macro isn't expanded again inside its own replacement, however deeply it is nested.
+        assert_eq!(expand(&table, "X"), Ok("X + 1".to_string()));
+        assert_eq!(expand(&table, "P Q"), Ok("P Q".to_string()));
+        assert_eq!(expand(&table, "R"), Ok("[ R ( R ) ]".to_string()));
+
+        // it is expanded again once its replacement is done with.
+        assert_eq!(expand(&table, "X X"), Ok("X + 1 X + 1".to_string()));
+    }
+    #[test]
+    fn function_like()
+    {
+        let mut table = Table::new();
+        function(&mut table, "F", &["a", "b"], "a + b");
+        function(&mut table, "ID", &["x"], "x");
+        object(&mut table, "A", "2");
+
+        assert_eq!(expand(&table, "F(1, (2, 3))"), Ok("1 + ( 2 , 3 )".to_string()));
+        assert_eq!(expand(&table, "F(\n1,\n2\n)"), Ok("1 + 2".to_string()));
+
+        // the name on its own isn't an invocation.
+        assert_eq!(expand(&table, "F + 1"), Ok("F + 1".to_string()));
+        assert_eq!(expand(&table, "F"), Ok("F".to_string()));
+
+        // arguments are expanded before they are substituted, and the result is rescanned.
+        assert_eq!(expand(&table, "ID(A)"), Ok("2".to_string()));
+        assert_eq!(expand(&table, "F(ID(A), ID(ID(3)))"), Ok("2 + 3".to_string()));
+
+        // an argument naming the macro ends up in its replacement, so it stays hidden.
+        assert_eq!(expand(&table, "ID(ID)(A)"), Ok("ID ( 2 )".to_string()));
+    }
+
+    #[test]
+    fn register_chain()
+    {
+        let mut table = Table::new();
+        function(&mut table, "_MMIO_BYTE", &["mem_addr"], "(*(volatile uint8_t *)(mem_addr))");
+        function(&mut table, "_SFR_IO8", &["io_addr"], "_MMIO_BYTE((io_addr) + 0x20)");
+        object(&mut table, "PINB", "_SFR_IO8(0x03)");
+
+        assert_eq!(expand(&table, "PINB"),
+                   Ok("( * ( volatile uint8_t * ) ( ( 0x03 ) + 0x20 ) )".to_string()));
+    }
+
+    #[test]
+    fn recursive_function_like()
+    {
+        let mut table = Table::new();
+        function(&mut table, "f", &["x"], "x * f(x)");
+        function(&mut table, "g", &["a"], "a * h");
+        function(&mut table, "h", &["a"], "g(a)");
+
+        assert_eq!(expand(&table, "f(2)"), Ok("2 * f ( 2 )".to_string()));
+        assert_eq!(expand(&table, "f(f(1))"), Ok("1 * f ( 1 ) * f ( 1 * f ( 1 ) )".to_string()));
+
+        // the standard's example: 'h' is only hidden from what comes out of its own replacement.
+        assert_eq!(expand(&table, "g(2)(9)"), Ok("2 * 9 * h".to_string()));
+    }
+
+    #[test]
+    fn empty_arguments()
+    {
+        let mut table = Table::new();
+        function(&mut table, "F", &["a", "b"], "[a|b]");
+        function(&mut table, "G", &["x"], "<x>");
+        function(&mut table, "H", &[], "h");
+
+        assert_eq!(expand(&table, "F(,)"), Ok("[ | ]".to_string()));
+        assert_eq!(expand(&table, "F(x,)"), Ok("[ x | ]".to_string()));
+        assert_eq!(expand(&table, "F(, y)"), Ok("[ | y ]".to_string()));
+        assert_eq!(expand(&table, "G()"), Ok("< >".to_string()));
+        assert_eq!(expand(&table, "H()"), Ok("h".to_string()));
+
+        assert_eq!(expand(&table, "F(1)").unwrap_err().code, Code::MacroArgumentCount);
+        assert_eq!(expand(&table, "G(1, 2)").unwrap_err().code, Code::MacroArgumentCount);
+        assert_eq!(expand(&table, "H(1)").unwrap_err().code, Code::MacroArgumentCount);
+        assert_eq!(expand(&table, "G(1").unwrap_err().code, Code::UnterminatedMacroInvocation);
+    }
+
+    #[test]
+    fn stringify()
+    {
+        let mut table = Table::new();
+        function(&mut table, "S", &["x"], "#x");
+        function(&mut table, "T", &["x"], "S(x)");
+        object(&mut table, "A", "2");
+        object(&mut table, "O", "#a");
+
+        assert_eq!(expand(&table, "S(a   +  b)"), Ok("\"a + b\"".to_string()));
+        assert_eq!(expand(&table, "S(  a  )"), Ok("\"a\"".to_string()));
+        assert_eq!(expand(&table, "S()"), Ok("\"\"".to_string()));
+
+        // quotes and backslashes are only escaped inside string literals and character constants.
+        assert_eq!(expand(&table, "S(\"a\\n\" '\"')"), Ok("\"\\\"a\\\\n\\\" '\\\"'\"".to_string()));
+
+        // the argument of '#' isn't expanded, unless it goes through another macro first.
+        assert_eq!(expand(&table, "S(A)"), Ok("\"A\"".to_string()));
+        assert_eq!(expand(&table, "T(A)"), Ok("\"2\"".to_string()));
+
+        // '#' is just a token in an object-like macro.
+        assert_eq!(expand(&table, "O"), Ok("# a".to_string()));
+    }
+
+    #[test]
+    fn paste()
+    {
+        let mut table = Table::new();
+        function(&mut table, "P", &["a", "b"], "a ## b");
+        function(&mut table, "Q", &["a", "b", "c"], "a ## b ## c");
+        function(&mut table, "W", &["x"], "L ## #x");
+        object(&mut table, "A", "2");
+        object(&mut table, "XY", "ok");
+
+        assert_eq!(expand(&table, "P(x, y)"), Ok("xy".to_string()));
+        assert_eq!(expand(&table, "P(1, 2)"), Ok("12".to_string()));
+        assert_eq!(expand(&table, "P(+, =)"), Ok("+=".to_string()));
+        assert_eq!(expand(&table, "P(x y, z w)"), Ok("x yz w".to_string()));
+        assert_eq!(expand(&table, "W(a)"), Ok("L\"a\"".to_string()));
+
+        // operands aren't expanded before they are pasted, but the result is rescanned.
+        assert_eq!(expand(&table, "P(A, A)"), Ok("AA".to_string()));
+        assert_eq!(expand(&table, "P(X, Y)"), Ok("ok".to_string()));
+
+        // an empty argument is a placemarker, which pastes to the other operand.
+        assert_eq!(expand(&table, "P(, y)"), Ok("y".to_string()));
+        assert_eq!(expand(&table, "P(x, )"), Ok("x".to_string()));
+        assert_eq!(expand(&table, "P(,)"), Ok("".to_string()));
+        assert_eq!(expand(&table, "Q(1, , 3)"), Ok("13".to_string()));
+        assert_eq!(expand(&table, "Q(, , )"), Ok("".to_string()));
+
+        // the pasted spelling has to be a single token.
+        assert_eq!(expand(&table, "P(+, -)").unwrap_err().code, Code::InvalidMacroOperator);
+        assert_eq!(expand(&table, "P(/, /)").unwrap_err().code, Code::InvalidMacroOperator);
+        assert_eq!(expand(&table, "P(., .)").unwrap_err().code, Code::InvalidMacroOperator);
+    }
+}
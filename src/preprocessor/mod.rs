@@ -3,5 +3,9 @@
 pub use self::preprocessor::Preprocessor;
 
 pub mod preprocessor;
+pub mod macros;
+pub mod expression;
+pub mod include;
+pub mod target;
 
 pub mod directives;
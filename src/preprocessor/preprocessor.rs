@@ -1,58 +1,980 @@
 
+use std;
+use std::collections::{HashMap,HashSet};
+
+use ast;
 use token;
-use token::{expect,Token,Tokenizer};
+use token::{expect,Token,Tokenizer,Punctuator,HeaderDelimiter};
+use token::tokenizer::Mode;
+use token::literal::Encoding;
 use preprocessor::directives;
+use preprocessor::directives::conditional;
+use preprocessor::macros;
+use preprocessor::macros::{PpToken,Input};
+use preprocessor::expression;
+use preprocessor::include;
+use preprocessor::target::Target;
+use source::Fallback;
+use location::Span;
+use standard::Standard;
+use diagnostic::{Diagnostic,Code,Severity};
 
+use time;
 use Identifier;
 
 #[deriving(Show)]
 pub enum Block
 {
-    Directive(directives::Directive),
+    /// A directive, along with the region of source it spans.
+    Directive(directives::Directive, Span),
+    /// A comment, which is only kept if the preprocessor is asked to.
+    Comment(ast::statements::Comment, Span),
     Token(Token),
 }
 
-pub struct Preprocessor<I: Iterator<char>>
+/// Settings which change how source is preprocessed.
+#[deriving(Clone,Show)]
+pub struct Options
+{
+    /// Whether comments are kept as `Block::Comment`s rather than being replaced by a space.
+    pub keep_comments: bool,
+    
+    /// The directories searched for headers, like GCC's '-I'.
+    /// Quoted names are looked for next to the including file first.
+    pub include_paths: Vec<Path>,
+    /// The directories searched after the include paths, like GCC's '-isystem'.
+    pub system_include_paths: Vec<Path>,
+    /// How deeply includes can be nested.
+    pub max_include_depth: uint,
+    /// How included files which aren't valid UTF-8 are read.
+    pub fallback: Fallback,
+    
+    /// The name the main source is reported as, such as by `__FILE__`.
+    pub file_name: Option<String>,
+    /// The compiler and machine whose predefined macros are defined, if any.
+    pub target: Option<Target>,
+    /// Whether the implementation is hosted rather than freestanding, for `__STDC_HOSTED__`.
+    pub hosted: bool,
+}
+
+impl Options
+{
+    /// Gets the default options.
+    pub fn new() -> Options
+    {
+        Options {
+            keep_comments: false,
+            
+            include_paths: Vec::new(),
+            system_include_paths: Vec::new(),
+            max_include_depth: 200,
+            fallback: Fallback::Strict,
+            
+            file_name: None,
+            target: None,
+            hosted: true,
+        }
+    }
+}
+
+/// A conditional group which hasn't been closed by '#endif' yet.
+struct Group
 {
-    it: Tokenizer<I>,
+    /// Whether the source in the current branch is included.
+    active: bool,
+    /// Whether one of the branches has been taken, so the rest are skipped.
+    /// Groups inside skipped source count as taken already.
+    taken: bool,
+    /// Whether '#else' has been seen.
+    has_else: bool,
+    /// The span of the directive which opened the group.
+    span: Span,
+}
+
+/// How far through the include guard idiom a file is.
+/// A guarded file starts with '#ifndef X' and ends with the matching '#endif'.
+enum Guard
+{
+    /// Nothing has been read yet.
+    Start,
+    /// The file started with '#ifndef X', whose group is at a given depth.
+    Open(Identifier, uint),
+    /// The '#endif' of the guard has been read, and nothing since.
+    Closed(Identifier),
+    /// There is something outside of the guard, if there is one at all.
+    Unguarded,
+}
+
+/// What we know about a file being preprocessed, apart from its tokens.
+struct File
+{
+    /// The path it was read from, which is nothing for the main source.
+    path: Option<Path>,
+    /// The name the file is reported as, which can be changed by '#line'.
+    name: Option<String>,
+    /// The index of the search path it was found in, for '#include_next'.
+    search_index: Option<uint>,
+    /// The number of conditional groups which were open when it was included.
+    depth: uint,
+    guard: Guard,
+}
+
+impl File
+{
+    fn new(path: Option<Path>, search_index: Option<uint>, depth: uint) -> File
+    {
+        File {
+            name: path.as_ref().map(|path| path.display().to_string()),
+            path: path,
+            search_index: search_index,
+            depth: depth,
+            guard: Guard::Start,
+        }
+    }
+}
+
+pub struct Preprocessor<'a>
+{
+    it: Tokenizer<'a>,
+    options: Options,
+    
+    /// Where included files are read into, if including files is allowed.
+    files: Option<&'a include::Files>,
+    /// The file being read.
+    file: File,
+    /// The files which included the current one, along with where we were in them, innermost last.
+    includes: Vec<(Tokenizer<'a>, File)>,
+    /// The macro guarding each file which has an include guard.
+    guards: HashMap<Path, Identifier>,
+    /// The files which had '#pragma once'.
+    once: HashSet<Path>,
+    
+    /// The macros which are defined.
+    macros: macros::Table,
+    /// Tokens from macro expansions which are still to be rescanned, in reverse order.
+    pending: Vec<PpToken>,
+    /// Diagnostics which were found alongside something else, such as warnings.
+    diagnostics: Vec<Diagnostic>,
+    /// The conditional groups we are in, innermost last.
+    groups: Vec<Group>,
+    
+    /// The span of the last token that was consumed by a directive.
+    last_span: Span,
+    /// Whether a fatal error has been given, after which nothing more is read.
+    stopped: bool,
 }
 
-impl<I: Iterator<char>> Preprocessor<I>
+impl<'a> Preprocessor<'a>
 {
-    pub fn new(it: Tokenizer<I>) -> Preprocessor<I>
+    pub fn new(it: Tokenizer<'a>) -> Preprocessor<'a>
+    {
+        Preprocessor::with_options(it, Options::new())
+    }
+    
+    pub fn with_options(mut it: Tokenizer<'a>, options: Options) -> Preprocessor<'a>
     {
-        Preprocessor {
+        it.set_comments(options.keep_comments);
+        
+        let mut preprocessor = Preprocessor {
             it: it,
+            options: options,
+            
+            files: None,
+            file: File::new(None, None, 0),
+            includes: Vec::new(),
+            guards: HashMap::new(),
+            once: HashSet::new(),
+            
+            macros: macros::Table::new(),
+            pending: Vec::new(),
+            diagnostics: Vec::new(),
+            groups: Vec::new(),
+            
+            last_span: Span::dummy(),
+            stopped: false,
+        };
+        
+        preprocessor.file.name = preprocessor.options.file_name.clone();
+        preprocessor.update_file_name();
+        preprocessor.predefine();
+        
+        preprocessor
+    }
+    
+    /// Creates a preprocessor which reads included files into `files`.
+    pub fn with_files(it: Tokenizer<'a>, options: Options, files: &'a include::Files) -> Preprocessor<'a>
+    {
+        let mut preprocessor = Preprocessor::with_options(it, options);
+        preprocessor.files = Some(files);
+        
+        preprocessor
+    }
+    
+    /// Defines the macros which are there before any source is read.
+    fn predefine(&mut self)
+    {
+        let mut predefined = vec![
+            ("__STDC__", "1".to_string()),
+            ("__STDC_HOSTED__", match self.options.hosted { true => "1", false => "0" }.to_string()),
+        ];
+        
+        // C89 has no '__STDC_VERSION__'.
+        let version = match self.it.standard() {
+            Standard::C89 => None,
+            Standard::C99 => Some("199901L"),
+            Standard::C11 => Some("201112L"),
+            Standard::C17 => Some("201710L"),
+            Standard::C23 => Some("202311L"),
+        };
+        
+        match version {
+            Some(version) => predefined.push(("__STDC_VERSION__", version.to_string())),
+            None => (),
+        }
+        
+        // the date and time preprocessing started, as in "Jan  1 2015" and "13:05:09".
+        let now = time::now();
+        let months = ["Jan", "Feb", "Mar", "Apr", "May", "Jun", "Jul", "Aug", "Sep", "Oct", "Nov", "Dec"];
+        
+        predefined.push(("__DATE__", format!("\"{} {:2} {}\"", months[now.tm_mon as uint], now.tm_mday, now.tm_year + 1900)));
+        predefined.push(("__TIME__", format!("\"{:02}:{:02}:{:02}\"", now.tm_hour, now.tm_min, now.tm_sec)));
+        
+        match self.options.target {
+            Some(ref target) => {
+                predefined.extend(target.macros().into_iter().map(|(name, value)| (name, value.to_string())));
+            },
+            None => (),
+        }
+        
+        let options = token::tokenizer::Options {
+            standard: self.it.standard(),
+            .. token::tokenizer::Options::new()
+        };
+        
+        for (name, value) in predefined.into_iter() {
+            let body: Vec<Token> = Tokenizer::with_options(value.as_slice(), options.clone())
+                .filter_map(|result| result.ok())
+                .collect();
+            
+            let body = match body.len() {
+                0 => None,
+                _ => Some(body),
+            };
+            
+            self.macros.define(directives::Define::constant(Identifier { name: name.to_string() }, body));
+        }
+        
+        self.macros.define_builtin(Identifier { name: "__FILE__".to_string() }, macros::Builtin::File);
+        self.macros.define_builtin(Identifier { name: "__LINE__".to_string() }, macros::Builtin::Line);
+        self.macros.define_builtin(Identifier { name: "__COUNTER__".to_string() }, macros::Builtin::Counter);
+    }
+    
+    /// Gives `__FILE__` the name of the current file.
+    fn update_file_name(&mut self)
+    {
+        let name = self.file.name.clone().unwrap_or("<stdin>".to_string());
+        self.macros.set_file(name);
+    }
+    
+    /// Adds a macro to the table.
+    fn define(&mut self, define: directives::Define, span: &Span)
+    {
+        match self.macros.define(define.clone()) {
+            Some(ref previous) if !previous.is_equivalent(&define) => {
+                self.diagnostics.push(Diagnostic::warning(Code::MacroRedefined,
+                                                          format!("'{}' redefined", define.name.name))
+                                          .with_span(span.clone()));
+            },
+            _ => (),
+        }
+    }
+    
+    /// Peeks at the next token of a directive, skipping comments.
+    fn peek_token(&mut self) -> Option<Result<Token,Diagnostic>>
+    {
+        loop {
+            match self.it.peek() {
+                Some(Ok(ref token)) if is_comment(token) => self.it.eat(),
+                result => { return result; },
+            }
         }
     }
     
-    fn preprocess_directive(&mut self) -> Result<Block,String>
+    /// Reads the next token of a directive, skipping comments.
+    fn next_token(&mut self) -> Option<Result<Token,Diagnostic>>
     {
-        expect::assert_token(self.it.next(), &Token::hash());
+        let result = self.peek_token();
+        self.it.eat();
         
-        match try!(expect::kind(self.it.next(), token::Kind::Word)) {
-            Token(token::Kind::Word, ref word) => match word.as_slice() {
+        result
+    }
+    
+    /// Checks whether the current source is in a conditional group which is skipped.
+    fn is_skipping(&self) -> bool
+    {
+        self.groups.last().map(|group| !group.active).unwrap_or(false)
+    }
+    
+    /// Preprocesses a directive, giving nothing if it is in skipped source.
+    fn preprocess_directive(&mut self) -> Result<Option<Block>,Diagnostic>
+    {
+        let hash = expect::assert_token(self.next_token(), &Token::hash());
+        self.macros.set_line(hash.span.start.line);
+        
+        let directive = match self.preprocess_directive_body(&hash.span) {
+            Ok(Some(directive)) => directive,
+            Ok(None) => { return Ok(None); },
+            Err(diagnostic) => {
+                // carry on from the next line.
+                self.skip_directive();
+                return Err(diagnostic);
+            },
+        };
+        
+        // the directive spans up to the start of the next line.
+        let span = hash.span.to(&self.last_span);
+        
+        match directive {
+            directives::Directive::Define(ref define) => self.define(define.clone(), &span),
+            _ => (),
+        }
+        
+        Ok(Some(Block::Directive(directive, span)))
+    }
+    
+    fn preprocess_directive_body(&mut self, hash: &Span) -> Result<Option<directives::Directive>,Diagnostic>
+    {
+        // only conditional directives matter in skipped source, and the rest may not even be valid.
+        if self.is_skipping() {
+            let is_conditional = match self.peek_token() {
+                Some(Ok(ref name)) => name.is_identifier() && conditional::Kind::from_name(name.value()).is_some(),
+                _ => false,
+            };
             
-                "define" => self.preprocess_define(),
-                d => { return Err(format!("unknown directive: {}", d)); },
+            if !is_conditional {
+                self.skip_directive();
+                return Ok(None);
+            }
+        }
+        
+        // a '#' on its own does nothing.
+        match self.peek_token() {
+            Some(Ok(Token { kind: token::Kind::EndOfDirective, .. })) => {
+                self.skip_directive();
+                return Ok(Some(directives::Directive::Null));
+            },
+            _ => (),
+        }
+        
+        let name = try!(expect::identifier(self.next_token()));
+        
+        match conditional::Kind::from_name(name.value()) {
+            Some(kind) => { return self.preprocess_conditional(kind, name.value(), hash.to(&name.span)); },
+            None => (),
+        }
+        
+        self.mark_unguarded();
+        
+        match name.value() {
+            "define" => self.preprocess_define().map(|directive| Some(directive)),
+            "include" => self.preprocess_include(false, hash.to(&name.span)).map(|directive| Some(directive)),
+            "include_next" => self.preprocess_include(true, hash.to(&name.span)).map(|directive| Some(directive)),
+            "undef" => self.preprocess_undef().map(|directive| Some(directive)),
+            "line" => self.preprocess_line().map(|directive| Some(directive)),
+            "error" => self.preprocess_message(Code::ErrorDirective, "error", hash.to(&name.span)).map(|directive| Some(directive)),
+            "warning" => self.preprocess_message(Code::WarningDirective, "warning", hash.to(&name.span)).map(|directive| Some(directive)),
+            "pragma" => {
+                let tokens = try!(self.read_directive_tokens());
+                Ok(Some(directives::Directive::Pragma(self.pragma(tokens))))
+            },
+            d => Err(Diagnostic::error(Code::UnknownDirective, format!("unknown directive '{}'", d))
+                         .with_span(name.span.clone())),
+        }
+    }
+    
+    /// Reads the end of a directive, warning about anything else left on the line.
+    fn end_directive(&mut self, name: &str)
+    {
+        match self.peek_token() {
+            Some(Ok(Token { kind: token::Kind::EndOfDirective, .. })) => (),
+            Some(Ok(token)) => {
+                self.diagnostics.push(Diagnostic::warning(Code::UnexpectedToken,
+                                                          format!("extra tokens at the end of '#{}'", name))
+                                          .with_span(token.span));
+            },
+            _ => (),
+        }
+        
+        self.skip_directive();
+    }
+    
+    /// Skips the rest of the current directive.
+    fn skip_directive(&mut self)
+    {
+        // the tokenizer goes back to reading code once it has read the end of
+        // the directive, though we may not have consumed it yet.
+        while self.it.mode() == Mode::Directive {
+            match self.it.next() {
+                Some(..) => (),
+                None => break,
+            }
+        }
+        
+        // only a token which was already read is looked at, so that an error
+        // on the next line is left to be given when that line is read.
+        if !self.it.has_peeked() {
+            return;
+        }
+        
+        match self.it.peek() {
+            Some(Ok(Token { kind: token::Kind::EndOfDirective, span, .. })) => {
+                self.it.eat();
+                self.last_span = span;
+            },
+            _ => (),
+        }
+    }
+    
+    /// Preprocesses a conditional directive, opening, moving along or closing a group.
+    /// `span` covers the '#' and the name of the directive.
+    fn preprocess_conditional(&mut self, kind: conditional::Kind, name: &str, span: Span)
+        -> Result<Option<directives::Directive>,Diagnostic>
+    {
+        if kind.is_opening() {
+            let outer_active = !self.is_skipping();
+            
+            // '#ifndef' may be the start of an include guard.
+            if kind != conditional::Kind::Ifndef {
+                self.mark_unguarded();
+            }
+            
+            // the condition of a group inside skipped source isn't looked at.
+            let condition = match outer_active {
+                true => self.evaluate_condition(&kind, name, &span),
+                false => {
+                    self.skip_directive();
+                    Ok(false)
+                },
+            };
+            
+            // the group is opened even if the condition is invalid, so that its '#endif' matches.
+            let taken = match condition {
+                Ok(taken) => taken,
+                Err(..) => false,
+            };
+            
+            self.groups.push(Group {
+                active: taken,
+                taken: taken || !outer_active,
+                has_else: false,
+                span: span,
+            });
             
+            try!(condition);
+            
+            return match outer_active {
+                true => Ok(Some(directives::Directive::Conditional(directives::Conditional::new(kind, taken)))),
+                false => Ok(None),
+            };
+        }
+        
+        // the groups opened by an including file can't be closed from inside the included one.
+        let group = match self.groups.len() > self.file.depth {
+            true => self.groups.pop().unwrap(),
+            false => {
+                return Err(Diagnostic::error(Code::UnmatchedConditional, format!("'#{}' without '#if'", name))
+                               .with_span(span));
             },
-            _ => unreachable!(),
+        };
+        
+        if group.has_else && (kind != conditional::Kind::Endif) {
+            self.groups.push(group);
+            
+            return Err(Diagnostic::error(Code::UnmatchedConditional, format!("'#{}' after '#else'", name))
+                           .with_span(span));
+        }
+        
+        self.update_guard(&kind);
+        
+        let outer_active = !self.is_skipping();
+        
+        let active = match kind {
+            conditional::Kind::Endif => {
+                self.end_directive(name);
+                outer_active
+            },
+            conditional::Kind::Else => {
+                self.end_directive(name);
+                
+                self.groups.push(Group {
+                    active: !group.taken,
+                    taken: true,
+                    has_else: true,
+                    span: group.span,
+                });
+                
+                !group.taken
+            },
+            _ => {
+                let condition = match group.taken {
+                    true => {
+                        self.skip_directive();
+                        Ok(false)
+                    },
+                    false => self.evaluate_condition(&kind, name, &span),
+                };
+                
+                let taken = match condition {
+                    Ok(taken) => taken,
+                    Err(..) => false,
+                };
+                
+                self.groups.push(Group {
+                    active: taken,
+                    taken: group.taken || taken,
+                    has_else: false,
+                    span: group.span,
+                });
+                
+                try!(condition)
+            },
+        };
+        
+        match outer_active {
+            true => Ok(Some(directives::Directive::Conditional(directives::Conditional::new(kind, active)))),
+            false => Ok(None),
+        }
+    }
+    
+    /// Works out the condition of an '#if', '#ifdef' or similar directive.
+    fn evaluate_condition(&mut self, kind: &conditional::Kind, name: &str, span: &Span) -> Result<bool,Diagnostic>
+    {
+        let expected = match *kind {
+            conditional::Kind::If | conditional::Kind::Elif => {
+                let tokens = try!(self.read_directive_tokens());
+                return expression::evaluate(&self.macros, self.it.standard(), tokens, span);
+            },
+            conditional::Kind::Ifdef | conditional::Kind::Elifdef => true,
+            _ => false,
+        };
+        
+        let (identifier, _) = try!(self.read_identifier());
+        self.end_directive(name);
+        
+        if *kind == conditional::Kind::Ifndef {
+            self.open_guard(&identifier);
+        }
+        
+        Ok(self.macros.is_defined(&identifier) == expected)
+    }
+    
+    /// Notes that the current file has something outside of its include guard.
+    fn mark_unguarded(&mut self)
+    {
+        let is_open = match self.file.guard {
+            Guard::Open(..) => true,
+            _ => false,
+        };
+        
+        if !is_open {
+            self.file.guard = Guard::Unguarded;
         }
     }
     
-    fn preprocess_define(&mut self) -> Result<Block,String>
+    /// Starts the include guard of the current file, if nothing has been read before its '#ifndef'.
+    fn open_guard(&mut self, name: &Identifier)
     {
-        let name_str = try!(expect::kind(self.it.next(), token::Kind::Word)).move_value();
+        let is_start = match self.file.guard {
+            Guard::Start => true,
+            _ => false,
+        };
         
-        let name = match Identifier::from_name(name_str) {
-            Some(name) => name,
-            None => { return Err("invalid identifier".to_string()); },
+        // the group of the '#ifndef' is opened once its condition is known.
+        match is_start {
+            true => { self.file.guard = Guard::Open(name.clone(), self.groups.len()); },
+            false => self.mark_unguarded(),
+        }
+    }
+    
+    /// Moves the include guard along once a conditional directive has closed the current branch of a group.
+    /// The guard is closed by the '#endif' of its group, but any other branch means there isn't a guard.
+    fn update_guard(&mut self, kind: &conditional::Kind)
+    {
+        let closes_guard = match self.file.guard {
+            Guard::Open(_, depth) => depth == self.groups.len(),
+            _ => false,
         };
         
-        match try!(expect::something(self.it.peek())) {
-            Token(token::Kind::Symbol, ref sym) if sym.as_slice() == "(" => {
-                unimplemented!();
+        if !closes_guard {
+            self.mark_unguarded();
+            return;
+        }
+        
+        let guard = std::mem::replace(&mut self.file.guard, Guard::Unguarded);
+        
+        self.file.guard = match (guard, *kind == conditional::Kind::Endif) {
+            (Guard::Open(name, _), true) => Guard::Closed(name),
+            _ => Guard::Unguarded,
+        };
+    }
+    
+    /// Preprocesses an '#include' or '#include_next', carrying on in the header
+    /// unless it has been read before and is guarded.
+    /// `span` covers the '#' and the name of the directive.
+    fn preprocess_include(&mut self, next: bool, span: Span) -> Result<directives::Directive,Diagnostic>
+    {
+        let tokens = try!(self.read_directive_tokens());
+        let (name, delimiter) = try!(self.read_header_name(tokens, &span));
+        
+        let files = match self.files {
+            Some(files) => files,
+            None => {
+                return Err(Diagnostic::fatal(Code::HeaderNotFound,
+                                             format!("can't include '{}', as including files isn't allowed", name))
+                               .with_span(span));
+            },
+        };
+        
+        let search_paths: Vec<Path> = self.options.include_paths.iter()
+            .chain(self.options.system_include_paths.iter())
+            .map(|path| path.clone())
+            .collect();
+        
+        // '#include_next' carries on after the search path the current file was found in,
+        // and is just like '#include' in a file which wasn't found in one.
+        let (current, start) = match (next, self.file.search_index) {
+            (true, Some(index)) => (None, index + 1),
+            // quoted names in the main source are looked for in the working directory.
+            _ => (Some(self.file.path.as_ref().map(|path| path.dir_path()).unwrap_or(Path::new("."))), 0),
+        };
+        
+        let (path, search_index) = match include::find(name.as_slice(), delimiter.clone(), current.as_ref(), search_paths.as_slice(), start) {
+            Some(found) => found,
+            None => {
+                return Err(Diagnostic::fatal(Code::HeaderNotFound, format!("'{}' not found", name))
+                               .with_span(span));
+            },
+        };
+        
+        let skipped = self.once.contains(&path) ||
+                      self.guards.get(&path).map(|guard| self.macros.is_defined(guard)).unwrap_or(false);
+        
+        if !skipped {
+            try!(self.enter_file(files, path.clone(), search_index, &span));
+        }
+        
+        Ok(directives::Directive::Include(directives::Include {
+            name: name,
+            delimiter: delimiter,
+            next: next,
+            
+            path: path,
+            skipped: skipped,
+        }))
+    }
+    
+    /// Gets the name of the header from the tokens of an '#include'.
+    /// If they aren't a header name already, they are macro-expanded, and
+    /// should then be a string literal or tokens between '<' and '>'.
+    fn read_header_name(&mut self, tokens: Vec<Token>, span: &Span) -> Result<(String, HeaderDelimiter),Diagnostic>
+    {
+        if tokens.len() == 1 {
+            match tokens[0].kind {
+                token::Kind::HeaderName(ref delimiter) => {
+                    let value = tokens[0].value.as_slice();
+                    return Ok((value.slice(1, value.len() - 1).to_string(), delimiter.clone()));
+                },
+                _ => (),
+            }
+        }
+        
+        let pp_tokens = tokens.into_iter().map(|token| PpToken::new(token)).collect();
+        let expanded: Vec<Token> = try!(macros::expand_all(&self.macros, self.it.standard(), pp_tokens))
+            .into_iter()
+            .map(|pp_token| pp_token.token)
+            .collect();
+        
+        let is_string = (expanded.len() == 1) && match expanded[0].kind {
+            token::Kind::StringLiteral(..) => expanded[0].value.as_slice().starts_with("\""),
+            _ => false,
+        };
+        
+        let is_angled = (expanded.len() >= 2) &&
+                        expanded[0].is_punctuator(Punctuator::Less) &&
+                        expanded[expanded.len() - 1].is_punctuator(Punctuator::Greater);
+        
+        if is_string {
+            let value = expanded[0].value.as_slice();
+            Ok((value.slice(1, value.len() - 1).to_string(), HeaderDelimiter::Quotes))
+        } else if is_angled {
+            // the spellings of the tokens are joined, with a space wherever there was whitespace.
+            let mut name = String::new();
+            
+            for (i, token) in expanded.slice(1, expanded.len() - 1).iter().enumerate() {
+                if (i > 0) && token.leading_space {
+                    name.push(' ');
+                }
+                
+                name.push_str(token.value.as_slice());
+            }
+            
+            Ok((name, HeaderDelimiter::AngleBrackets))
+        } else {
+            Err(Diagnostic::error(Code::InvalidHeaderName, "expected \"FILENAME\" or <FILENAME>".to_string())
+                    .with_span(span.clone()))
+        }
+    }
+    
+    /// Starts reading an included file, putting the current one aside until it is finished.
+    fn enter_file(&mut self, files: &'a include::Files, path: Path, search_index: Option<uint>, span: &Span)
+        -> Result<(),Diagnostic>
+    {
+        if self.includes.len() >= self.options.max_include_depth {
+            return Err(Diagnostic::fatal(Code::IncludeDepthExceeded,
+                                         format!("includes nested more than {} deep", self.options.max_include_depth))
+                           .with_span(span.clone()));
+        }
+        
+        let (source, id) = match files.read(&path, self.options.fallback.clone()) {
+            Ok(read) => read,
+            Err(diagnostic) => { return Err(diagnostic.with_span(span.clone())); },
+        };
+        
+        let options = token::tokenizer::Options {
+            file: id,
+            .. self.it.options()
+        };
+        
+        let file = File::new(Some(path), search_index, self.groups.len());
+        
+        let it = std::mem::replace(&mut self.it, source.tokenizer(options));
+        let file = std::mem::replace(&mut self.file, file);
+        self.includes.push((it, file));
+        self.update_file_name();
+        
+        Ok(())
+    }
+    
+    /// Goes back to reading the file which included the current one.
+    fn leave_file(&mut self, it: Tokenizer<'a>, file: File)
+    {
+        let finished = std::mem::replace(&mut self.file, file);
+        self.it = it;
+        self.update_file_name();
+        
+        // a file which is all inside an include guard doesn't need to be read again while its macro is defined.
+        match (finished.path, finished.guard) {
+            (Some(path), Guard::Closed(name)) => { self.guards.insert(path, name); },
+            _ => (),
+        }
+    }
+    
+    /// Creates a pragma from the tokens of a '#pragma' or '_Pragma'.
+    /// Only '#pragma once' is acted on here, and the rest are left to whatever reads them.
+    fn pragma(&mut self, tokens: Vec<Token>) -> directives::Pragma
+    {
+        let pragma = directives::Pragma::new(tokens);
+        
+        if pragma.is_once() {
+            match self.file.path {
+                Some(ref path) => { self.once.insert(path.clone()); },
+                None => (),
+            }
+        }
+        
+        pragma
+    }
+    
+    /// Preprocesses a '_Pragma("...")' operator, just after the '_Pragma'.
+    /// The string literal is turned back into source, which is tokenized as if it were a '#pragma'.
+    fn preprocess_pragma_operator(&mut self, name: Token) -> Result<Block,Diagnostic>
+    {
+        let tokens = {
+            let mut input = SourceInput {
+                pending: &mut self.pending,
+                it: &mut self.it,
+            };
+            
+            let mut tokens = Vec::new();
+            
+            for _ in range(0u, 3) {
+                match input.next_token() {
+                    Some(Ok(pp_token)) => tokens.push(pp_token.token),
+                    Some(Err(diagnostic)) => { return Err(diagnostic); },
+                    None => break,
+                }
+            }
+            
+            tokens
+        };
+        
+        let is_string = match tokens.get(1) {
+            Some(&Token { kind: token::Kind::StringLiteral(ref literal), .. }) => {
+                (literal.encoding == Encoding::Plain) || (literal.encoding == Encoding::Wide)
+            },
+            _ => false,
+        };
+        
+        let is_valid = (tokens.len() == 3) && is_string &&
+                       tokens[0].is_punctuator(Punctuator::LeftParenthesis) &&
+                       tokens[2].is_punctuator(Punctuator::RightParenthesis);
+        
+        if !is_valid {
+            return Err(Diagnostic::error(Code::UnexpectedToken,
+                                         "expected a parenthesized string literal after '_Pragma'".to_string())
+                           .with_span(name.span.clone()));
+        }
+        
+        let span = name.span.to(&tokens[2].span);
+        
+        // an 'L' prefix and the quotes are dropped, and '\"' and '\\' are unescaped.
+        let spelling = tokens[1].value.as_slice().trim_left_chars('L');
+        let mut text = String::new();
+        let mut chars = spelling.slice(1, spelling.len() - 1).chars().peekable();
+        
+        loop {
+            let c = match chars.next() {
+                Some(c) => c,
+                None => break,
+            };
+            
+            let is_escape = (c == '\\') && match chars.peek() {
+                Some(&'"') | Some(&'\\') => true,
+                _ => false,
+            };
+            
+            match is_escape {
+                true => text.push(chars.next().unwrap()),
+                false => text.push(c),
+            }
+        }
+        
+        let options = token::tokenizer::Options {
+            file: name.span.file.clone(),
+            standard: self.it.standard(),
+            .. token::tokenizer::Options::new()
+        };
+        
+        let mut tokens = Vec::new();
+        
+        for result in Tokenizer::with_options(text.as_slice(), options) {
+            let mut token = try!(result);
+            
+            // the tokens aren't anywhere in the source, so they are given the span of the operator.
+            token.span = span.clone();
+            tokens.push(token);
+        }
+        
+        Ok(Block::Directive(directives::Directive::Pragma(self.pragma(tokens)), span))
+    }
+    
+    fn preprocess_undef(&mut self) -> Result<directives::Directive,Diagnostic>
+    {
+        let (name, _) = try!(self.read_identifier());
+        self.end_directive("undef");
+        
+        self.macros.undefine(&name);
+        Ok(directives::Directive::Undef(name))
+    }
+    
+    /// Preprocesses an '#error' or '#warning', whose message is given as a diagnostic.
+    /// `span` covers the '#' and the name of the directive.
+    fn preprocess_message(&mut self, code: Code, name: &str, span: Span) -> Result<directives::Directive,Diagnostic>
+    {
+        let message = try!(self.it.read_rest_of_line());
+        
+        let (diagnostic, directive) = match code {
+            Code::ErrorDirective => {
+                (Diagnostic::fatal(code, format!("#error {}", message)), directives::Directive::Error(message))
+            },
+            _ => {
+                (Diagnostic::warning(code, format!("#warning {}", message)), directives::Directive::Warning(message))
+            },
+        };
+        
+        self.end_directive(name);
+        self.diagnostics.push(diagnostic.with_span(span));
+        
+        Ok(directive)
+    }
+    
+    /// Preprocesses a '#line', which gives the number of the next line and optionally a new file name.
+    /// The tokens are macro-expanded if they aren't in that form already.
+    /// The new line number shows up in the spans of later tokens, but the file name is only
+    /// used for `__FILE__`, as spans refer to the file they were read from by its id.
+    fn preprocess_line(&mut self) -> Result<directives::Directive,Diagnostic>
+    {
+        let tokens = try!(self.read_directive_tokens());
+        let span = tokens.first().map(|token| token.span.clone()).unwrap_or(self.last_span.clone());
+        
+        let pp_tokens = tokens.into_iter().map(|token| PpToken::new(token)).collect();
+        let tokens: Vec<Token> = try!(macros::expand_all(&self.macros, self.it.standard(), pp_tokens))
+            .into_iter()
+            .map(|pp_token| pp_token.token)
+            .collect();
+        
+        // the line number is a decimal digit sequence, even if it starts with 0.
+        let line = match tokens.first() {
+            Some(&Token { kind: token::Kind::PpNumber, ref value, .. }) if value.as_slice().chars().all(|c| c.is_digit(10)) => {
+                std::str::from_str::<uint>(value.as_slice())
+            },
+            _ => None,
+        };
+        
+        let file = match tokens.get(1) {
+            Some(&Token { kind: token::Kind::StringLiteral(ref literal), .. }) if literal.encoding == Encoding::Plain => {
+                // the name is the value of the string, with escape sequences replaced.
+                let bytes: Vec<u8> = literal.code_units.iter().map(|&unit| unit as u8).collect();
+                Some(String::from_utf8_lossy(bytes.as_slice()).into_owned())
+            },
+            _ => None,
+        };
+        
+        let expected_length = match file {
+            Some(..) => 2,
+            None => 1,
+        };
+        
+        // C89 only allows line numbers up to 32767.
+        let limit = match self.it.standard() {
+            Standard::C89 => 32767u,
+            _ => 2147483647u,
+        };
+        
+        let line = match line {
+            Some(line) if (line > 0) && (line <= limit) && (tokens.len() == expected_length) => line,
+            _ => {
+                return Err(Diagnostic::error(Code::InvalidLineDirective,
+                                             format!("expected a line number from 1 to {} and an optional file name", limit))
+                               .with_span(span));
+            },
+        };
+        
+        self.it.set_line(line);
+        
+        match file {
+            Some(ref file) => {
+                self.file.name = Some(file.clone());
+                self.update_file_name();
+            },
+            None => (),
+        }
+        
+        Ok(directives::Directive::Line(directives::Line {
+            line: line,
+            file: file,
+        }))
+    }
+    
+    fn preprocess_define(&mut self) -> Result<directives::Directive,Diagnostic>
+    {
+        let (name, _) = try!(self.read_identifier());
+        
+        // a function-like macro has its parameter list straight after the name.
+        // with a space in between, the parenthesis is part of the body.
+        match try!(expect::something(self.peek_token())) {
+            Token { kind: token::Kind::Punctuator(Punctuator::LeftParenthesis), leading_space: false, .. } => {
+                self.preprocess_define_function(name)
             },
             _ => {
                 self.preprocess_define_constant(name)
@@ -60,23 +982,135 @@ impl<I: Iterator<char>> Preprocessor<I>
         }
     }
     
-    fn preprocess_define_constant(&mut self, name: Identifier) -> Result<Block,String>
+    /// Reads an identifier, giving back its span too.
+    fn read_identifier(&mut self) -> Result<(Identifier, Span),Diagnostic>
     {
+        let token = try!(expect::identifier(self.next_token()));
+        let span = token.span.clone();
+        
+        match Identifier::from_name(token.move_value(), self.it.standard()) {
+            Ok(identifier) => Ok((identifier, span)),
+            Err(diagnostic) => Err(diagnostic.with_span(span)),
+        }
+    }
+    
+    fn preprocess_define_function(&mut self, name: Identifier) -> Result<directives::Directive,Diagnostic>
+    {
+        let (params, variadic) = try!(self.read_define_params());
         let body = try!(self.read_define_body());
         
-        Ok(Block::Directive(directives::Directive::Define(
-            directives::Define::constant(name, body)
-        )))
+        let define = directives::Define::function(name, params, variadic, body);
+        try!(macros::check_body(&define, self.it.standard()));
+        
+        Ok(directives::Directive::Define(define))
     }
     
-    fn read_define_body(&mut self) -> Result<Option<Vec<Token>>,String>
+    /// Reads the parameter list of a function-like macro, such as '(a, b)'.
+    /// The last parameter may be variadic, written as '...' or GNU style as 'name...'.
+    /// Gives back the parameters, and whether the macro is variadic.
+    fn read_define_params(&mut self) -> Result<(Vec<Identifier>, bool),Diagnostic>
+    {
+        expect::assert_token(self.next_token(), &Token::left_parenthesis());
+        
+        let mut params: Vec<(Identifier, Span)> = Vec::new();
+        
+        // the list may be empty.
+        match try!(expect::something(self.peek_token())) {
+            Token { kind: token::Kind::Punctuator(Punctuator::RightParenthesis), .. } => {
+                self.it.eat();
+                return Ok((Vec::new(), false));
+            },
+            _ => (),
+        }
+        
+        let mut variadic = false;
+        
+        loop {
+            let (param, span) = match try!(expect::something(self.peek_token())) {
+                Token { kind: token::Kind::Punctuator(Punctuator::Ellipsis), span, .. } => {
+                    self.it.eat();
+                    variadic = true;
+                    
+                    (Identifier { name: "__VA_ARGS__".to_string() }, span)
+                },
+                _ => try!(self.read_identifier()),
+            };
+            
+            if !variadic && (param.name.as_slice() == "__VA_ARGS__") {
+                return Err(Diagnostic::error(Code::InvalidVariadicUse,
+                                             "'__VA_ARGS__' can only be used in a variadic macro".to_string())
+                               .with_span(span));
+            }
+            
+            match params.iter().find(|&&(ref existing, _)| existing == &param) {
+                Some(&(_, ref first_span)) => {
+                    return Err(Diagnostic::error(Code::DuplicateMacroParameter,
+                                                 format!("duplicate macro parameter '{}'", param.name))
+                                   .with_span(span)
+                                   .with_label(first_span.clone(), "first used here".to_string()));
+                },
+                None => (),
+            }
+            
+            params.push((param, span));
+            
+            // a named variadic parameter is followed straight away by '...'.
+            if !variadic {
+                match try!(expect::something(self.peek_token())) {
+                    Token { kind: token::Kind::Punctuator(Punctuator::Ellipsis), .. } => {
+                        self.it.eat();
+                        variadic = true;
+                    },
+                    _ => (),
+                }
+            }
+            
+            // nothing may follow the variadic parameter.
+            if variadic {
+                try!(expect::token(self.next_token(), &Token::right_parenthesis()));
+                break;
+            }
+            
+            let separator = try!(expect::one_of(self.next_token(), [Token::comma(), Token::right_parenthesis()].iter()));
+            
+            if separator == Token::right_parenthesis() {
+                break;
+            }
+        }
+        
+        Ok((params.into_iter().map(|(param, _)| param).collect(), variadic))
+    }
+    
+    fn preprocess_define_constant(&mut self, name: Identifier) -> Result<directives::Directive,Diagnostic>
+    {
+        let body = try!(self.read_define_body());
+        
+        let define = directives::Define::constant(name, body);
+        try!(macros::check_body(&define, self.it.standard()));
+        
+        Ok(directives::Directive::Define(define))
+    }
+    
+    fn read_define_body(&mut self) -> Result<Option<Vec<Token>>,Diagnostic>
+    {
+        let body = try!(self.read_directive_tokens());
+        
+        match body.len() {
+            0 => Ok(None),
+            _ => Ok(Some(body)),
+        }
+    }
+    
+    /// Reads the rest of the tokens of a directive.
+    fn read_directive_tokens(&mut self) -> Result<Vec<Token>,Diagnostic>
     {
         let mut result = Vec::new();
         
         loop {
-            match try!(expect::something(self.it.peek())) {
-                Token(token::Kind::NewLine,_) => {
+            match try!(expect::something(self.peek_token())) {
+                Token { kind: token::Kind::EndOfDirective, span, .. } => {
                     self.it.eat();
+                    self.last_span = span;
                     break;
                 },
                 token => {
@@ -87,42 +1121,458 @@ impl<I: Iterator<char>> Preprocessor<I>
             }
         }
         
-        match result.len() {
-            0 => Ok(None),
-            _ => Ok(Some(result)),
-        }
+        Ok(result)
     }
 }
 
-impl<I: Iterator<char>> Iterator<Result<Block,String>> for Preprocessor<I>
+impl<'a> Preprocessor<'a>
 {
-    fn next(&mut self) -> Option<Result<Block,String>>
+    /// Gets the next block, whether or not a fatal error has been given.
+    fn next_block(&mut self) -> Option<Result<Block,Diagnostic>>
     {
-        match self.it.peek() {
-            Some(Ok(tok)) => match tok {
-                Token(token::Kind::Symbol, ref symbol) if (symbol.as_slice() == "#") => {
-                    Some(self.preprocess_directive())
-                },
-                Token(token::Kind::Symbol, ref symbol) if (symbol.as_slice() == "//") => {
-                    unimplemented!();
-                },
-                Token(token::Kind::Symbol, ref symbol) if (symbol.as_slice() == "/*") => {
-                    unimplemented!();
+        loop {
+            // warnings about tokens which were read anyway come before anything else.
+            let warnings = self.it.take_warnings();
+            self.diagnostics.extend(warnings.into_iter());
+            
+            match self.diagnostics.remove(0) {
+                Some(diagnostic) => { return Some(Err(diagnostic)); },
+                None => (),
+            }
+            
+            // tokens from macro expansions are rescanned before reading any more source.
+            let pp_token = match self.pending.pop() {
+                Some(pp_token) => pp_token,
+                None => match self.it.peek() {
+                    Some(Ok(tok)) => match tok {
+                        // a '#' starts a directive only if it begins a line.
+                        Token { kind: token::Kind::Punctuator(Punctuator::Hash), line_start: true, .. } => {
+                            self.it.set_mode(Mode::Directive);
+                            
+                            match self.preprocess_directive() {
+                                Ok(Some(block)) => { return Some(Ok(block)); },
+                                Ok(None) => { continue; },
+                                Err(diagnostic) => { return Some(Err(diagnostic)); },
+                            }
+                        },
+                        // skipped source is thrown away.
+                        _ if self.is_skipping() => {
+                            self.it.eat();
+                            self.it.take_warnings();
+                            continue;
+                        },
+                        Token { kind: token::Kind::LineComment, .. } |
+                        Token { kind: token::Kind::BlockComment, .. } => {
+                            self.it.eat();
+                            
+                            match self.options.keep_comments {
+                                true => { return Some(Ok(to_comment(tok))); },
+                                false => { continue; },
+                            }
+                        },
+                        // the end of the source only carries trivia, which isn't kept once preprocessed.
+                        Token { kind: token::Kind::EndOfFile, .. } => {
+                            self.it.eat();
+                            continue;
+                        },
+                        // an error the tokenizer recovered from is given as a diagnostic, not a token.
+                        Token { kind: token::Kind::Error(diagnostic), .. } => {
+                            self.it.eat();
+                            return Some(Err(diagnostic));
+                        },
+                        // it's just a regular token.
+                        _ => {
+                            self.it.eat(); // chew on the token so we don't choke next iteration
+                            self.mark_unguarded();
+                            self.macros.set_line(tok.span.start.line);
+                            
+                            PpToken::new(tok)
+                        }
+                    },
+                    // skipped source may hold anything at all.
+                    Some(Err(..)) if self.is_skipping() => {
+                        continue;
+                    },
+                    Some(Err(err)) => {
+                        return Some(Err(err));
+                    },
+                    None => {
+                        // every group has to be closed by the end of the file it was opened in.
+                        if self.groups.len() > self.file.depth {
+                            let group = self.groups.pop().unwrap();
+                            
+                            return Some(Err(Diagnostic::error(Code::UnterminatedConditional,
+                                                              "unterminated conditional directive".to_string())
+                                                .with_span(group.span)));
+                        }
+                        
+                        match self.includes.pop() {
+                            Some((it, file)) => {
+                                self.leave_file(it, file);
+                                continue;
+                            },
+                            None => { return None; },
+                        }
+                    }
                 },
-                // it's just a regular token - pass it on.
-                _ => {
-                    self.it.eat(); // chew on the token so we don't choke next iteration
+            };
+            
+            let standard = self.it.standard();
+            
+            let expanded = {
+                let mut input = SourceInput {
+                    pending: &mut self.pending,
+                    it: &mut self.it,
+                };
+                
+                macros::expand(&self.macros, standard, pp_token, &mut input)
+            };
+            
+            match expanded {
+                Ok(Some(pp_token)) => {
+                    // '_Pragma' is only looked for once macros are expanded, as it can come from one.
+                    let is_pragma = pp_token.token.is_identifier() && (pp_token.token.value.as_slice() == "_Pragma");
                     
-                    Some(Ok(Block::Token(tok)))
-                }
-            },
-            Some(Err(err)) => {
-                return Some(Err(err));
-            },
-            None => {
-                return None;
+                    return match is_pragma {
+                        true => Some(self.preprocess_pragma_operator(pp_token.token)),
+                        false => Some(Ok(Block::Token(pp_token.token))),
+                    };
+                },
+                Ok(None) => (),
+                Err(diagnostic) => { return Some(Err(diagnostic)); },
+            }
+        }
+    }
+}
+
+impl<'a> Iterator<Result<Block,Diagnostic>> for Preprocessor<'a>
+{
+    /// Gets the next block.
+    /// Diagnostics are given as they are found, after which preprocessing carries on,
+    /// unless they are fatal. Some of them are only warnings, which can be told apart
+    /// with `Diagnostic::is_error`.
+    fn next(&mut self) -> Option<Result<Block,Diagnostic>>
+    {
+        if self.stopped {
+            return None;
+        }
+        
+        let block = self.next_block();
+        
+        match block {
+            Some(Err(ref diagnostic)) if diagnostic.severity == Severity::Fatal => { self.stopped = true; },
+            _ => (),
+        }
+        
+        block
+    }
+}
+
+
+/// The input of the preprocessor, when expanding macros in code: the tokens
+/// left over from earlier expansions, followed by the rest of the source.
+struct SourceInput<'p, 'a: 'p>
+{
+    pending: &'p mut Vec<PpToken>,
+    it: &'p mut Tokenizer<'a>,
+}
+
+impl<'p, 'a> macros::Input for SourceInput<'p, 'a>
+{
+    fn next_token(&mut self) -> Option<Result<PpToken,Diagnostic>>
+    {
+        match self.pending.pop() {
+            Some(pp_token) => { return Some(Ok(pp_token)); },
+            None => (),
+        }
+        
+        loop {
+            match self.it.next() {
+                Some(Ok(ref token)) if is_comment(token) => (),
+                result => { return result.map(|result| result.map(|token| PpToken::new(token))); },
             }
         }
     }
+    
+    fn peek_token(&mut self) -> Option<Result<PpToken,Diagnostic>>
+    {
+        match self.pending.last() {
+            Some(pp_token) => { return Some(Ok(pp_token.clone())); },
+            None => (),
+        }
+        
+        loop {
+            match self.it.peek() {
+                Some(Ok(ref token)) if is_comment(token) => self.it.eat(),
+                result => { return result.map(|result| result.map(|token| PpToken::new(token))); },
+            }
+        }
+    }
+    
+    fn push_tokens(&mut self, tokens: Vec<PpToken>)
+    {
+        self.pending.extend(tokens.into_iter().rev());
+    }
+}
+
+fn is_comment(token: &Token) -> bool
+{
+    match token.kind {
+        token::Kind::LineComment | token::Kind::BlockComment => true,
+        _ => false,
+    }
+}
+
+/// Converts a comment token into a comment block, without its delimiters.
+fn to_comment(token: Token) -> Block
+{
+    let Token { kind, value, span, .. } = token;
+    
+    let comment = match kind {
+        token::Kind::LineComment => {
+            ast::statements::Comment(ast::statements::comment::Kind::KindLine, value.as_slice().slice_from(2).to_string())
+        },
+        _ => {
+            ast::statements::Comment(ast::statements::comment::Kind::KindBlock,
+                                     value.as_slice().slice(2, value.len() - 2).to_string())
+        },
+    };
+    
+    Block::Comment(comment, span)
 }
 
+#[cfg(test)]
+mod tests
+{
+    use std::io::{File,TempDir};
+    
+    use token::{tokenizer,Tokenizer};
+    use preprocessor::include;
+    use preprocessor::target::Target;
+    use standard::Standard;
+    use diagnostic::{Diagnostic,Code};
+    use super::{Preprocessor,Options,Block};
+    
+    /// Runs a preprocessor to the end, giving back the spelling of each token and every diagnostic.
+    fn collect(preprocessor: Preprocessor) -> (Vec<String>, Vec<Diagnostic>)
+    {
+        let mut tokens = Vec::new();
+        let mut diagnostics = Vec::new();
+        
+        for block in preprocessor {
+            match block {
+                Ok(Block::Token(token)) => tokens.push(token.value),
+                Ok(..) => (),
+                Err(diagnostic) => diagnostics.push(diagnostic),
+            }
+        }
+        
+        (tokens, diagnostics)
+    }
+    
+    fn preprocess(source: &str) -> (Vec<String>, Vec<Diagnostic>)
+    {
+        collect(Preprocessor::new(Tokenizer::new(source)))
+    }
+    
+    fn codes(diagnostics: &[Diagnostic]) -> Vec<Code>
+    {
+        diagnostics.iter().map(|diagnostic| diagnostic.code.clone()).collect()
+    }
+    
+    #[test]
+    fn va_opt_paste_at_edge()
+    {
+        let (_, diagnostics) = preprocess("#define F(...) __VA_OPT__(## x)\n#define G(...) __VA_OPT__(x ##)\n");
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::InvalidMacroOperator, Code::InvalidMacroOperator]);
+    }
+    
+    #[test]
+    fn va_opt_paste_inside()
+    {
+        let (tokens, diagnostics) = preprocess("#define F(a, ...) __VA_OPT__(a ## a) end\nF(x, 1) F(x)\n");
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![]);
+        assert_eq!(tokens, vec!["xx".to_string(), "end".to_string(), "end".to_string()]);
+    }
+    
+    #[test]
+    fn line_file_name()
+    {
+        let (tokens, diagnostics) = preprocess("#line 10 \"dir\\\\a.c\"\n__FILE__ __LINE__\n");
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![]);
+        assert_eq!(tokens, vec!["\"dir\\\\a.c\"".to_string(), "10".to_string()]);
+    }
+    
+    #[test]
+    fn endif_in_header()
+    {
+        let dir = TempDir::new("c-parser").unwrap();
+        File::create(&dir.path().join("a.h")).write_str("#endif\n").unwrap();
+        
+        let options = Options {
+            include_paths: vec![dir.path().clone()],
+            .. Options::new()
+        };
+        
+        let files = include::Files::new();
+        let source = "#if 1\n#include \"a.h\"\nx\n#endif\ny\n";
+        let (tokens, diagnostics) = collect(Preprocessor::with_files(Tokenizer::new(source), options, &files));
+        
+        // the header can't close the group it was included in.
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::UnmatchedConditional]);
+        assert_eq!(tokens, vec!["x".to_string(), "y".to_string()]);
+    }
+    
+    #[test]
+    fn error_directive_stops()
+    {
+        let (tokens, diagnostics) = preprocess("#error stop\nx\n");
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::ErrorDirective]);
+        assert_eq!(tokens, vec![]);
+    }
+    
+    #[test]
+    fn error_directive_comments()
+    {
+        let (_, diagnostics) = preprocess("#error stop /* at\nonce */ here // why\nx\n");
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::ErrorDirective]);
+        assert_eq!(diagnostics[0].message.as_slice(), "#error stop   here");
+    }
+    
+    #[test]
+    fn error_before_hash()
+    {
+        // the '#' isn't at the start of the line, so it doesn't start a directive.
+        let (tokens, diagnostics) = preprocess("@ #define X 1\nX\n");
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::UnknownCharacter]);
+        assert_eq!(tokens, vec!["#".to_string(), "define".to_string(), "X".to_string(), "1".to_string(), "X".to_string()]);
+    }
+    
+    #[test]
+    fn error_after_failed_directive()
+    {
+        // the error on the line after the directive isn't lost while skipping the rest of it.
+        let (tokens, diagnostics) = preprocess("#define 1\n@\nb\n");
+        
+        assert_eq!(diagnostics.len(), 2);
+        assert_eq!(diagnostics[1].code, Code::UnknownCharacter);
+        assert_eq!(tokens, vec!["b".to_string()]);
+    }    
+    #[test]
+    fn include_guard_with_trivia()
+    {
+        let dir = TempDir::new("c-parser").unwrap();
+        File::create(&dir.path().join("a.h")).write_str("// a.h\n#ifndef A_H\n#define A_H\nint a;\n#endif\n\n").unwrap();
+        
+        let options = Options {
+            include_paths: vec![dir.path().clone()],
+            .. Options::new()
+        };
+        
+        let tokenizer_options = tokenizer::Options {
+            trivia: true,
+            .. tokenizer::Options::new()
+        };
+        
+        let files = include::Files::new();
+        let source = "#include \"a.h\"\n#include \"a.h\"\nx\n";
+        let mut preprocessor = Preprocessor::with_files(Tokenizer::with_options(source, tokenizer_options), options, &files);
+        
+        let tokens: Vec<String> = preprocessor.by_ref().map(|block| match block.unwrap() {
+            Block::Token(token) => token.value,
+            _ => String::new(),
+        }).filter(|value| !value.is_empty()).collect();
+        
+        // the trivia at the end of the header doesn't count as being outside of its guard.
+        assert_eq!(tokens, vec!["int".to_string(), "a".to_string(), ";".to_string(), "x".to_string()]);
+        assert_eq!(preprocessor.guards.len(), 1);
+    }
+    
+    #[test]
+    fn recovered_errors()
+    {
+        let options = tokenizer::Options {
+            recover: true,
+            .. tokenizer::Options::new()
+        };
+        
+        let (tokens, diagnostics) = collect(Preprocessor::new(Tokenizer::with_options("a @ b\n", options)));
+        
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::UnknownCharacter]);
+        assert_eq!(tokens, vec!["a".to_string(), "b".to_string()]);
+    }    
+    #[test]
+    fn line_limit()
+    {
+        let source = "#line 32768\n__LINE__\n";
+        
+        let (tokens, diagnostics) = preprocess(source);
+        assert_eq!(codes(diagnostics.as_slice()), vec![]);
+        assert_eq!(tokens, vec!["32768".to_string()]);
+        
+        let options = tokenizer::Options {
+            standard: Standard::C89,
+            .. tokenizer::Options::new()
+        };
+        
+        let (_, diagnostics) = collect(Preprocessor::new(Tokenizer::with_options(source, options)));
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::InvalidLineDirective]);
+        
+        let (_, diagnostics) = preprocess("#line 2147483648\n");
+        assert_eq!(codes(diagnostics.as_slice()), vec![Code::InvalidLineDirective]);
+    }    
+    #[test]
+    fn stdc_version()
+    {
+        let versions = [
+            // C89 has no '__STDC_VERSION__', so it is left alone.
+            (Standard::C89, "__STDC_VERSION__"),
+            (Standard::C99, "199901L"),
+            (Standard::C11, "201112L"),
+            (Standard::C17, "201710L"),
+            (Standard::C23, "202311L"),
+        ];
+        
+        for &(ref standard, version) in versions.iter() {
+            let options = tokenizer::Options {
+                standard: standard.clone(),
+                .. tokenizer::Options::new()
+            };
+            
+            let (tokens, diagnostics) = collect(Preprocessor::new(Tokenizer::with_options("__STDC__ __STDC_VERSION__\n", options)));
+            
+            assert_eq!(codes(diagnostics.as_slice()), vec![]);
+            assert_eq!(tokens, vec!["1".to_string(), version.to_string()]);
+        }
+    }
+    
+    #[test]
+    fn target_branches()
+    {
+        let source = "#if defined(__AVR_ATmega328P__) && __SIZEOF_INT__ == 2\navr\n#elif __x86_64__\nx86_64\n#else\nnone\n#endif\n";
+        
+        let expected = [
+            (Some(Target::AvrGccAtmega328p), "avr"),
+            (Some(Target::GccX86_64Linux), "x86_64"),
+            (None, "none"),
+        ];
+        
+        for &(ref target, branch) in expected.iter() {
+            let options = Options {
+                target: target.clone(),
+                .. Options::new()
+            };
+            
+            let (tokens, diagnostics) = collect(Preprocessor::with_options(Tokenizer::new(source), options));
+            
+            assert_eq!(codes(diagnostics.as_slice()), vec![]);
+            assert_eq!(tokens, vec![branch.to_string()]);
+        }
+    }
+}
@@ -0,0 +1,186 @@
+
+//! The macros which compilers predefine for the machines they target, so that
+//! headers take the same branches as they would with the real compiler.
+
+/// A compiler and the machine it targets.
+#[deriving(Clone,PartialEq,Show)]
+pub enum Target
+{
+    /// GCC for x86-64 Linux.
+    GccX86_64Linux,
+    /// avr-gcc for the ATmega328P, as with '-mmcu=atmega328p'.
+    AvrGccAtmega328p,
+}
+
+impl Target
+{
+    /// Gets the macros the target predefines, as names and replacement text.
+    pub fn macros(&self) -> Vec<(&'static str, &'static str)>
+    {
+        let specific = match *self {
+            Target::GccX86_64Linux => GCC_X86_64_LINUX,
+            Target::AvrGccAtmega328p => AVR_GCC_ATMEGA328P,
+        };
+
+        GCC.iter().chain(specific.iter()).map(|&pair| pair).collect()
+    }
+}
+
+/// The macros every GCC target has.
+static GCC: &'static [(&'static str, &'static str)] = &[
+    ("__ELF__", "1"),
+    ("__CHAR_BIT__", "8"),
+    ("__SCHAR_MAX__", "0x7f"),
+    ("__SHRT_MAX__", "0x7fff"),
+    ("__LONG_LONG_MAX__", "0x7fffffffffffffffLL"),
+    ("__SIZEOF_SHORT__", "2"),
+    ("__SIZEOF_LONG_LONG__", "8"),
+    ("__SIZEOF_FLOAT__", "4"),
+    ("__ORDER_LITTLE_ENDIAN__", "1234"),
+    ("__ORDER_BIG_ENDIAN__", "4321"),
+    ("__ORDER_PDP_ENDIAN__", "3412"),
+    ("__BYTE_ORDER__", "__ORDER_LITTLE_ENDIAN__"),
+    ("__STDC_UTF_16__", "1"),
+    ("__STDC_UTF_32__", "1"),
+    ("__USER_LABEL_PREFIX__", ""),
+    ("__REGISTER_PREFIX__", ""),
+];
+
+static GCC_X86_64_LINUX: &'static [(&'static str, &'static str)] = &[
+    ("__GNUC__", "13"),
+    ("__GNUC_MINOR__", "2"),
+    ("__GNUC_PATCHLEVEL__", "0"),
+    ("__VERSION__", "\"13.2.0\""),
+    ("__x86_64__", "1"),
+    ("__x86_64", "1"),
+    ("__amd64__", "1"),
+    ("__amd64", "1"),
+    ("__linux__", "1"),
+    ("__linux", "1"),
+    ("__gnu_linux__", "1"),
+    ("__unix__", "1"),
+    ("__unix", "1"),
+    ("__LP64__", "1"),
+    ("_LP64", "1"),
+    ("__MMX__", "1"),
+    ("__SSE__", "1"),
+    ("__SSE2__", "1"),
+    ("__SIZEOF_INT__", "4"),
+    ("__SIZEOF_LONG__", "8"),
+    ("__SIZEOF_POINTER__", "8"),
+    ("__SIZEOF_DOUBLE__", "8"),
+    ("__SIZEOF_LONG_DOUBLE__", "16"),
+    ("__SIZEOF_SIZE_T__", "8"),
+    ("__SIZEOF_PTRDIFF_T__", "8"),
+    ("__SIZEOF_WCHAR_T__", "4"),
+    ("__SIZEOF_WINT_T__", "4"),
+    ("__INT_MAX__", "0x7fffffff"),
+    ("__LONG_MAX__", "0x7fffffffffffffffL"),
+    ("__WCHAR_MAX__", "0x7fffffff"),
+    ("__SIZE_MAX__", "0xffffffffffffffffUL"),
+    ("__INTMAX_MAX__", "0x7fffffffffffffffL"),
+    ("__SIZE_TYPE__", "long unsigned int"),
+    ("__PTRDIFF_TYPE__", "long int"),
+    ("__WCHAR_TYPE__", "int"),
+    ("__WINT_TYPE__", "unsigned int"),
+    ("__INTMAX_TYPE__", "long int"),
+    ("__UINTMAX_TYPE__", "long unsigned int"),
+    ("__CHAR16_TYPE__", "short unsigned int"),
+    ("__CHAR32_TYPE__", "unsigned int"),
+];
+
+/// avr-gcc has 16 bit ints and pointers, and 32 bit doubles.
+static AVR_GCC_ATMEGA328P: &'static [(&'static str, &'static str)] = &[
+    ("__GNUC__", "7"),
+    ("__GNUC_MINOR__", "3"),
+    ("__GNUC_PATCHLEVEL__", "0"),
+    ("__VERSION__", "\"7.3.0\""),
+    ("__AVR", "1"),
+    ("__AVR__", "1"),
+    ("__AVR_ATmega328P__", "1"),
+    ("__AVR_DEVICE_NAME__", "atmega328p"),
+    ("__AVR_ARCH__", "5"),
+    ("__AVR_MEGA__", "1"),
+    ("__AVR_ENHANCED__", "1"),
+    ("__AVR_HAVE_MUL__", "1"),
+    ("__AVR_HAVE_MOVW__", "1"),
+    ("__AVR_HAVE_LPMX__", "1"),
+    ("__AVR_HAVE_JMP_CALL__", "1"),
+    ("__AVR_2_BYTE_PC__", "1"),
+    ("__AVR_HAVE_16BIT_SP__", "1"),
+    ("__AVR_HAVE_SPH__", "1"),
+    ("__AVR_SFR_OFFSET__", "0x20"),
+    ("__SIZEOF_INT__", "2"),
+    ("__SIZEOF_LONG__", "4"),
+    ("__SIZEOF_POINTER__", "2"),
+    ("__SIZEOF_DOUBLE__", "4"),
+    ("__SIZEOF_LONG_DOUBLE__", "4"),
+    ("__SIZEOF_SIZE_T__", "2"),
+    ("__SIZEOF_PTRDIFF_T__", "2"),
+    ("__SIZEOF_WCHAR_T__", "2"),
+    ("__SIZEOF_WINT_T__", "2"),
+    ("__INT_MAX__", "0x7fff"),
+    ("__LONG_MAX__", "0x7fffffffL"),
+    ("__WCHAR_MAX__", "0x7fff"),
+    ("__SIZE_MAX__", "0xffffU"),
+    ("__INTMAX_MAX__", "0x7fffffffffffffffLL"),
+    ("__SIZE_TYPE__", "unsigned int"),
+    ("__PTRDIFF_TYPE__", "int"),
+    ("__WCHAR_TYPE__", "int"),
+    ("__WINT_TYPE__", "int"),
+    ("__INTMAX_TYPE__", "long long int"),
+    ("__UINTMAX_TYPE__", "long long unsigned int"),
+    ("__CHAR16_TYPE__", "unsigned int"),
+    ("__CHAR32_TYPE__", "long unsigned int"),
+];
+
+#[cfg(test)]
+mod tests
+{
+    use token::Tokenizer;
+    use super::Target;
+
+    static TARGETS: &'static [Target] = &[Target::GccX86_64Linux, Target::AvrGccAtmega328p];
+
+    fn value(target: &Target, name: &str) -> Option<&'static str>
+    {
+        target.macros().into_iter().find(|&(n, _)| n == name).map(|(_, value)| value)
+    }
+
+    #[test]
+    fn macros_are_well_formed()
+    {
+        for target in TARGETS.iter() {
+            let macros = target.macros();
+
+            for (i, &(name, value)) in macros.iter().enumerate() {
+                // the shared and specific tables mustn't both define a macro.
+                assert!(macros.slice_to(i).iter().all(|&(other, _)| other != name), "{} is defined twice", name);
+                assert!(name.starts_with("_"));
+                assert!(Tokenizer::new(value).all(|token| token.is_ok()), "{} has a bad value", name);
+            }
+        }
+    }
+
+    #[test]
+    fn target_specific_macros()
+    {
+        let avr = Target::AvrGccAtmega328p;
+        let x86_64 = Target::GccX86_64Linux;
+
+        assert_eq!(value(&avr, "__AVR__"), Some("1"));
+        assert_eq!(value(&avr, "__AVR_ATmega328P__"), Some("1"));
+        assert_eq!(value(&avr, "__SIZEOF_INT__"), Some("2"));
+        assert_eq!(value(&avr, "__SIZEOF_POINTER__"), Some("2"));
+        assert_eq!(value(&avr, "__x86_64__"), None);
+
+        assert_eq!(value(&x86_64, "__x86_64__"), Some("1"));
+        assert_eq!(value(&x86_64, "__SIZEOF_INT__"), Some("4"));
+        assert_eq!(value(&x86_64, "__SIZEOF_POINTER__"), Some("8"));
+        assert_eq!(value(&x86_64, "__AVR__"), None);
+
+        // both are GCC.
+        assert_eq!(value(&avr, "__CHAR_BIT__"), Some("8"));
+        assert_eq!(value(&x86_64, "__CHAR_BIT__"), Some("8"));
+    }
+}
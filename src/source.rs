@@ -0,0 +1,329 @@
+
+//! Reading and decoding source files.
+//!
+//! Source is read in chunks from any `Reader` and decoded as UTF-8, with an
+//! optional fallback for the Latin-1 and Windows-1252 files which are common
+//! in old vendor headers. The whole of the decoded text is kept in memory,
+//! and then borrowed by a `Tokenizer`; tokenizing doesn't start until the
+//! input has been read to the end.
+
+use std;
+use std::io::{Reader,EndOfFile};
+
+use token::Tokenizer;
+use token::tokenizer::Options;
+use diagnostic::{Diagnostic,Code};
+
+/// The size of the chunks source is read in.
+static CHUNK_SIZE: uint = 8192;
+
+/// What to do with bytes which aren't part of valid UTF-8.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum Fallback
+{
+    /// Report them as errors.
+    Strict,
+    /// Treat each of them as a Latin-1 (ISO 8859-1) character.
+    Latin1,
+    /// Treat each of them as a Windows-1252 character.
+    Windows1252,
+}
+
+/// Decoded source text.
+pub struct Source
+{
+    pub text: String,
+}
+
+impl Source
+{
+    /// Creates a source from text which has already been decoded.
+    pub fn new(text: String) -> Source
+    {
+        Source {
+            text: text,
+        }
+    }
+
+    /// Reads and decodes the whole of a source.
+    /// The input is read a chunk at a time, but only given back once it has all been decoded.
+    /// A UTF-8 byte order mark at the start is skipped.
+    pub fn read_to_end<R: Reader>(reader: &mut R, fallback: Fallback) -> Result<Source,Diagnostic>
+    {
+        let mut decoder = Decoder::new(fallback);
+        let mut buffer = [0u8, ..CHUNK_SIZE];
+
+        loop {
+            match reader.read(&mut buffer) {
+                Ok(length) => try!(decoder.push(buffer.slice_to(length))),
+                Err(ref error) if error.kind == EndOfFile => break,
+                Err(error) => {
+                    return Err(Diagnostic::error(Code::ReadError, format!("couldn't read source: {}", error))
+                                   .with_note(format!("after byte offset {}", decoder.offset)));
+                },
+            }
+        }
+
+        decoder.finish().map(|text| Source::new(text))
+    }
+
+    /// Creates a tokenizer over the source.
+    pub fn tokenizer<'a>(&'a self, options: Options) -> Tokenizer<'a>
+    {
+        Tokenizer::with_options(self.text.as_slice(), options)
+    }
+}
+
+/// Decodes UTF-8 which arrives in chunks.
+struct Decoder
+{
+    text: String,
+    fallback: Fallback,
+
+    /// The bytes of a character which was split across two chunks.
+    pending: Vec<u8>,
+    /// The offset of the first pending byte in the input.
+    offset: uint,
+}
+
+impl Decoder
+{
+    fn new(fallback: Fallback) -> Decoder
+    {
+        Decoder {
+            text: String::new(),
+            fallback: fallback,
+
+            pending: Vec::new(),
+            offset: 0,
+        }
+    }
+
+    /// Decodes a chunk of input.
+    fn push(&mut self, chunk: &[u8]) -> Result<(),Diagnostic>
+    {
+        let mut bytes = std::mem::replace(&mut self.pending, Vec::new());
+        bytes.push_all(chunk);
+
+        let mut pos = 0u;
+
+        // skip the byte order mark.
+        if self.offset == 0 && bytes.len() < 3 && [0xEFu8, 0xBB, 0xBF].starts_with(bytes.as_slice()) {
+            self.pending = bytes;
+            return Ok(());
+        } else if self.offset == 0 && bytes.as_slice().starts_with(&[0xEFu8, 0xBB, 0xBF]) {
+            pos = 3;
+        }
+
+        while pos < bytes.len() {
+            let length = match sequence_length(bytes[pos]) {
+                Some(length) => length,
+                None => {
+                    try!(self.fall_back(bytes[pos], pos));
+                    pos += 1;
+                    continue;
+                },
+            };
+
+            // the character carries on in the next chunk.
+            if pos + length > bytes.len() {
+                break;
+            }
+
+            match decode_sequence(bytes.slice(pos, pos + length)) {
+                Some(c) => {
+                    self.text.push(c);
+                    pos += length;
+                },
+                None => {
+                    try!(self.fall_back(bytes[pos], pos));
+                    pos += 1;
+                },
+            }
+        }
+
+        self.offset += pos;
+        self.pending = bytes.slice_from(pos).to_vec();
+
+        Ok(())
+    }
+
+    /// Finishes decoding, giving back the text.
+    fn finish(mut self) -> Result<String,Diagnostic>
+    {
+        // anything left over is a character which was cut short by the end of the input.
+        let pending = std::mem::replace(&mut self.pending, Vec::new());
+
+        for (i, &byte) in pending.iter().enumerate() {
+            try!(self.fall_back(byte, i));
+        }
+
+        Ok(self.text)
+    }
+
+    /// Decodes a byte which isn't part of valid UTF-8 with the fallback encoding.
+    /// `pos` is the position of the byte in the bytes being decoded.
+    fn fall_back(&mut self, byte: u8, pos: uint) -> Result<(),Diagnostic>
+    {
+        let c = match self.fallback {
+            Fallback::Strict => {
+                return Err(Diagnostic::error(Code::InvalidEncoding,
+                                             format!("invalid UTF-8 byte 0x{:02X} at byte offset {}", byte, self.offset + pos))
+                               .with_note("the source can be read as Latin-1 or Windows-1252 instead".to_string()));
+            },
+            Fallback::Latin1 => byte as char,
+            Fallback::Windows1252 => windows_1252(byte),
+        };
+
+        self.text.push(c);
+        Ok(())
+    }
+}
+
+/// Gets the length of a UTF-8 sequence from its first byte.
+fn sequence_length(byte: u8) -> Option<uint>
+{
+    match byte {
+        0x00...0x7F => Some(1),
+        0xC2...0xDF => Some(2),
+        0xE0...0xEF => Some(3),
+        0xF0...0xF4 => Some(4),
+        _ => None,
+    }
+}
+
+/// Decodes a complete UTF-8 sequence.
+/// Gives nothing for overlong sequences, surrogates and bad continuation bytes.
+fn decode_sequence(bytes: &[u8]) -> Option<char>
+{
+    let (mut value, minimum) = match bytes.len() {
+        1 => { return Some(bytes[0] as char); },
+        2 => ((bytes[0] & 0x1F) as u32, 0x80u32),
+        3 => ((bytes[0] & 0x0F) as u32, 0x800u32),
+        _ => ((bytes[0] & 0x07) as u32, 0x10000u32),
+    };
+
+    for &byte in bytes.slice_from(1).iter() {
+        if (byte & 0xC0) != 0x80 {
+            return None;
+        }
+
+        value = (value << 6) | ((byte & 0x3F) as u32);
+    }
+
+    match value >= minimum {
+        true => std::char::from_u32(value),
+        false => None,
+    }
+}
+
+/// Gets the character a byte stands for in Windows-1252.
+/// It is the same as Latin-1, apart from 0x80 to 0x9F.
+fn windows_1252(byte: u8) -> char
+{
+    match byte {
+        0x80 => '€',
+        0x82 => '‚',
+        0x83 => 'ƒ',
+        0x84 => '„',
+        0x85 => '…',
+        0x86 => '†',
+        0x87 => '‡',
+        0x88 => 'ˆ',
+        0x89 => '‰',
+        0x8A => 'Š',
+        0x8B => '‹',
+        0x8C => 'Œ',
+        0x8E => 'Ž',
+        0x91 => '‘',
+        0x92 => '’',
+        0x93 => '“',
+        0x94 => '”',
+        0x95 => '•',
+        0x96 => '–',
+        0x97 => '—',
+        0x98 => '˜',
+        0x99 => '™',
+        0x9A => 'š',
+        0x9B => '›',
+        0x9C => 'œ',
+        0x9E => 'ž',
+        0x9F => 'Ÿ',
+        // the bytes which Windows-1252 leaves undefined are read as Latin-1, like browsers do.
+        byte => byte as char,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use std::io::MemReader;
+    use diagnostic::Code;
+    use super::{Source,Decoder,Fallback,CHUNK_SIZE};
+
+    fn read(bytes: &[u8], fallback: Fallback) -> String
+    {
+        Source::read_to_end(&mut MemReader::new(bytes.to_vec()), fallback).unwrap().text
+    }
+
+    #[test]
+    fn byte_order_mark()
+    {
+        assert_eq!(read(b"\xEF\xBB\xBFint a;", Fallback::Strict).as_slice(), "int a;");
+        assert_eq!(read(b"\xEF\xBB\xBF", Fallback::Strict).as_slice(), "");
+
+        // only a mark at the very start is skipped.
+        assert_eq!(read(b"a\xEF\xBB\xBF", Fallback::Strict).as_slice(), "a\uFEFF");
+    }
+
+    #[test]
+    fn characters_split_between_chunks()
+    {
+        for c in ['é', '€', '😀'].iter() {
+            let mut buffer = [0u8, ..4];
+            let length = c.encode_utf8(&mut buffer).unwrap();
+
+            // every split of the character across the end of the first chunk.
+            for before in range(CHUNK_SIZE + 1 - length, CHUNK_SIZE) {
+                let mut bytes = Vec::from_elem(before, b'a');
+                bytes.push_all(buffer.slice_to(length));
+                bytes.push(b'b');
+
+                let text = read(bytes.as_slice(), Fallback::Strict);
+                assert_eq!(text.len(), before + length + 1);
+                assert_eq!(text.as_slice().char_at(before), *c);
+            }
+        }
+    }
+
+    #[test]
+    fn byte_order_mark_split_between_chunks()
+    {
+        let mut decoder = Decoder::new(Fallback::Strict);
+        decoder.push(b"\xEF").unwrap();
+        decoder.push(b"\xBB").unwrap();
+        decoder.push(b"\xBFa").unwrap();
+        assert_eq!(decoder.finish().unwrap().as_slice(), "a");
+
+        // a mark cut short by the end of the input is just invalid.
+        let result = Source::read_to_end(&mut MemReader::new(vec![0xEFu8, 0xBB]), Fallback::Strict);
+        assert_eq!(result.err().unwrap().code, Code::InvalidEncoding);
+
+        assert_eq!(read(b"\xEF\xBB", Fallback::Latin1).as_slice(), "\u00EF\u00BB");
+    }
+
+    #[test]
+    fn invalid_bytes()
+    {
+        let mut bytes = Vec::from_elem(CHUNK_SIZE + 10, b'a');
+        bytes.push(0x80);
+
+        let result = Source::read_to_end(&mut MemReader::new(bytes), Fallback::Strict);
+        let diagnostic = result.err().unwrap();
+        assert_eq!(diagnostic.code, Code::InvalidEncoding);
+        assert!(diagnostic.message.as_slice().contains(format!("byte offset {}", CHUNK_SIZE + 10).as_slice()));
+
+        assert_eq!(read(b"caf\xE9 \x80", Fallback::Latin1).as_slice(), "caf\u00E9 \u0080");
+        assert_eq!(read(b"caf\xE9 \x80", Fallback::Windows1252).as_slice(), "caf\u00E9 \u20AC");
+    }
+}
@@ -0,0 +1,20 @@
+
+/// A version of the C language standard.
+#[deriving(Clone,PartialEq,Eq,PartialOrd,Ord,Show)]
+pub enum Standard
+{
+    C89,
+    C99,
+    C11,
+    C17,
+    C23,
+}
+
+impl Standard
+{
+    /// Gets the standard used when none is asked for.
+    pub fn default() -> Standard
+    {
+        Standard::C17
+    }
+}
@@ -0,0 +1,180 @@
+
+use std::collections::HashMap;
+use std::rc::Rc;
+
+use standard::Standard;
+
+/// A keyword.
+/// Keywords with more than one spelling (such as `_Bool` and `bool`) share a variant.
+#[deriving(Clone,PartialEq,Eq,Hash,Show)]
+pub enum Keyword
+{
+    // C89.
+    Auto,
+    Break,
+    Case,
+    Char,
+    Const,
+    Continue,
+    Default,
+    Do,
+    Double,
+    Else,
+    Enum,
+    Extern,
+    Float,
+    For,
+    Goto,
+    If,
+    Int,
+    Long,
+    Register,
+    Return,
+    Short,
+    Signed,
+    Sizeof,
+    Static,
+    Struct,
+    Switch,
+    Typedef,
+    Union,
+    Unsigned,
+    Void,
+    Volatile,
+    While,
+
+    // C99.
+    Inline,
+    Restrict,
+    Bool,
+    Complex,
+    Imaginary,
+
+    // C11.
+    Alignas,
+    Alignof,
+    Atomic,
+    Generic,
+    Noreturn,
+    StaticAssert,
+    ThreadLocal,
+
+    // C23.
+    Constexpr,
+    False,
+    Nullptr,
+    True,
+    Typeof,
+    TypeofUnqual,
+    BitInt,
+    Decimal32,
+    Decimal64,
+    Decimal128,
+}
+
+/// Every keyword spelling, along with the standard which introduced it.
+static KEYWORDS: &'static [(&'static str, Keyword, Standard)] = &[
+    ("auto", Keyword::Auto, Standard::C89),
+    ("break", Keyword::Break, Standard::C89),
+    ("case", Keyword::Case, Standard::C89),
+    ("char", Keyword::Char, Standard::C89),
+    ("const", Keyword::Const, Standard::C89),
+    ("continue", Keyword::Continue, Standard::C89),
+    ("default", Keyword::Default, Standard::C89),
+    ("do", Keyword::Do, Standard::C89),
+    ("double", Keyword::Double, Standard::C89),
+    ("else", Keyword::Else, Standard::C89),
+    ("enum", Keyword::Enum, Standard::C89),
+    ("extern", Keyword::Extern, Standard::C89),
+    ("float", Keyword::Float, Standard::C89),
+    ("for", Keyword::For, Standard::C89),
+    ("goto", Keyword::Goto, Standard::C89),
+    ("if", Keyword::If, Standard::C89),
+    ("int", Keyword::Int, Standard::C89),
+    ("long", Keyword::Long, Standard::C89),
+    ("register", Keyword::Register, Standard::C89),
+    ("return", Keyword::Return, Standard::C89),
+    ("short", Keyword::Short, Standard::C89),
+    ("signed", Keyword::Signed, Standard::C89),
+    ("sizeof", Keyword::Sizeof, Standard::C89),
+    ("static", Keyword::Static, Standard::C89),
+    ("struct", Keyword::Struct, Standard::C89),
+    ("switch", Keyword::Switch, Standard::C89),
+    ("typedef", Keyword::Typedef, Standard::C89),
+    ("union", Keyword::Union, Standard::C89),
+    ("unsigned", Keyword::Unsigned, Standard::C89),
+    ("void", Keyword::Void, Standard::C89),
+    ("volatile", Keyword::Volatile, Standard::C89),
+    ("while", Keyword::While, Standard::C89),
+
+    ("inline", Keyword::Inline, Standard::C99),
+    ("restrict", Keyword::Restrict, Standard::C99),
+    ("_Bool", Keyword::Bool, Standard::C99),
+    ("_Complex", Keyword::Complex, Standard::C99),
+    ("_Imaginary", Keyword::Imaginary, Standard::C99),
+
+    ("_Alignas", Keyword::Alignas, Standard::C11),
+    ("_Alignof", Keyword::Alignof, Standard::C11),
+    ("_Atomic", Keyword::Atomic, Standard::C11),
+    ("_Generic", Keyword::Generic, Standard::C11),
+    ("_Noreturn", Keyword::Noreturn, Standard::C11),
+    ("_Static_assert", Keyword::StaticAssert, Standard::C11),
+    ("_Thread_local", Keyword::ThreadLocal, Standard::C11),
+
+    ("alignas", Keyword::Alignas, Standard::C23),
+    ("alignof", Keyword::Alignof, Standard::C23),
+    ("bool", Keyword::Bool, Standard::C23),
+    ("constexpr", Keyword::Constexpr, Standard::C23),
+    ("false", Keyword::False, Standard::C23),
+    ("nullptr", Keyword::Nullptr, Standard::C23),
+    ("static_assert", Keyword::StaticAssert, Standard::C23),
+    ("thread_local", Keyword::ThreadLocal, Standard::C23),
+    ("true", Keyword::True, Standard::C23),
+    ("typeof", Keyword::Typeof, Standard::C23),
+    ("typeof_unqual", Keyword::TypeofUnqual, Standard::C23),
+    ("_BitInt", Keyword::BitInt, Standard::C23),
+    ("_Decimal32", Keyword::Decimal32, Standard::C23),
+    ("_Decimal64", Keyword::Decimal64, Standard::C23),
+    ("_Decimal128", Keyword::Decimal128, Standard::C23),
+];
+
+thread_local!(static TABLE: Rc<Table> = Rc::new(Table::new()))
+
+/// The keywords of every standard, along with the standard which introduced each of them.
+pub struct Table
+{
+    keywords: HashMap<&'static str, (Keyword, Standard)>,
+}
+
+impl Table
+{
+    /// Builds the keyword table.
+    /// Tokenizers share a single table through `Table::shared`, rather than building their own.
+    pub fn new() -> Table
+    {
+        let mut keywords = HashMap::new();
+
+        for &(spelling, ref keyword, ref introduced) in KEYWORDS.iter() {
+            keywords.insert(spelling, (keyword.clone(), introduced.clone()));
+        }
+
+        Table {
+            keywords: keywords,
+        }
+    }
+
+    /// Gets the keyword table, which is only built once on each thread.
+    pub fn shared() -> Rc<Table>
+    {
+        TABLE.with(|table| table.clone())
+    }
+
+    /// Looks up a word, giving back the keyword it spells in a standard, if any.
+    pub fn lookup(&self, word: &str, standard: &Standard) -> Option<Keyword>
+    {
+        match self.keywords.get(word) {
+            Some(&(ref keyword, ref introduced)) if introduced <= standard => Some(keyword.clone()),
+            _ => None,
+        }
+    }
+}
@@ -0,0 +1,321 @@
+
+use std;
+use util::IteratorPeeker;
+use diagnostic::{Diagnostic,Code};
+
+/// A single element of a decoded character or string literal.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum Unit
+{
+    /// A character, written directly or through an escape sequence.
+    Char(char),
+    /// A value given by an octal or hexadecimal escape sequence.
+    /// These are stored as-is, rather than as characters.
+    Raw(u32),
+}
+
+/// The encoding of a literal, given by its prefix.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum Encoding
+{
+    /// No prefix, giving a `char` array.
+    Plain,
+    /// `u8`, giving a UTF-8 `char` (or `char8_t` in C23) array.
+    Utf8,
+    /// `u`, giving a `char16_t` array.
+    Utf16,
+    /// `U`, giving a `char32_t` array.
+    Utf32,
+    /// `L`, giving a `wchar_t` array.
+    /// `wchar_t` is taken to be 32 bits wide, holding UTF-32.
+    Wide,
+}
+
+impl Encoding
+{
+    /// Gets the encoding for a literal prefix.
+    pub fn from_prefix(prefix: &str) -> Option<Encoding>
+    {
+        match prefix {
+            "" => Some(Encoding::Plain),
+            "u8" => Some(Encoding::Utf8),
+            "u" => Some(Encoding::Utf16),
+            "U" => Some(Encoding::Utf32),
+            "L" => Some(Encoding::Wide),
+            _ => None,
+        }
+    }
+
+    /// Gets the prefix which is written before the literal.
+    pub fn prefix(&self) -> &'static str
+    {
+        match *self {
+            Encoding::Plain => "",
+            Encoding::Utf8 => "u8",
+            Encoding::Utf16 => "u",
+            Encoding::Utf32 => "U",
+            Encoding::Wide => "L",
+        }
+    }
+
+    /// Gets the largest value a single code unit can hold.
+    pub fn max_unit(&self) -> u32
+    {
+        match *self {
+            Encoding::Plain | Encoding::Utf8 => 0xFF,
+            Encoding::Utf16 => 0xFFFF,
+            Encoding::Utf32 | Encoding::Wide => 0xFFFFFFFF,
+        }
+    }
+}
+
+/// The decoded value of a character or string literal.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub struct Literal
+{
+    pub encoding: Encoding,
+    pub units: Vec<Unit>,
+    /// The value of the literal as a sequence of code units of its encoding,
+    /// not including the terminating null of a string.
+    pub code_units: Vec<u32>,
+}
+
+/// Decodes the body of a character or string literal (the text between the quotes).
+pub fn decode(body: &str, encoding: Encoding) -> Result<Literal,Diagnostic>
+{
+    let mut units = Vec::new();
+    let mut it = IteratorPeeker::new(body.chars());
+    
+    loop {
+        match it.next() {
+            Some('\\') => {
+                let unit = try!(decode_escape(&mut it));
+                units.push(unit);
+            },
+            Some(c) => units.push(Unit::Char(c)),
+            None => break,
+        }
+    }
+    
+    let code_units = try!(encode(units.as_slice(), &encoding));
+
+    Ok(Literal {
+        encoding: encoding,
+        units: units,
+        code_units: code_units,
+    })
+}
+
+/// Encodes the units of a literal into code units.
+/// Characters are encoded as UTF-8, UTF-16 or UTF-32, and raw values are kept as they are.
+fn encode(units: &[Unit], encoding: &Encoding) -> Result<Vec<u32>,Diagnostic>
+{
+    let mut code_units = Vec::new();
+    
+    for unit in units.iter() {
+        match *unit {
+            Unit::Char(c) => match *encoding {
+                Encoding::Plain | Encoding::Utf8 => {
+                    let mut buffer = [0u8, ..4];
+                    let length = c.encode_utf8(&mut buffer).unwrap();
+                    
+                    code_units.extend(buffer.slice_to(length).iter().map(|&b| b as u32));
+                },
+                Encoding::Utf16 => {
+                    let mut buffer = [0u16, ..2];
+                    let length = c.encode_utf16(&mut buffer).unwrap();
+                    
+                    code_units.extend(buffer.slice_to(length).iter().map(|&u| u as u32));
+                },
+                Encoding::Utf32 | Encoding::Wide => code_units.push(c as u32),
+            },
+            Unit::Raw(value) => {
+                if value > encoding.max_unit() {
+                    return Err(Diagnostic::error(Code::CharacterOutOfRange,
+                                                 format!("escape sequence value 0x{:X} is out of range", value)));
+                }
+                
+                code_units.push(value);
+            },
+        }
+    }
+    
+    Ok(code_units)
+}
+
+/// Checks that a universal character name refers to a character which
+/// is allowed to be written that way.
+pub fn universal_character(value: u32) -> Result<char,Diagnostic>
+{
+    // C11 6.4.3: a universal character name can't name a surrogate, or anything
+    // in the basic character set other than '$', '@' and '`'.
+    let allowed = match value {
+        0x24 | 0x40 | 0x60 => true,
+        0xD800...0xDFFF => false,
+        v => v >= 0xA0,
+    };
+    
+    match std::char::from_u32(value) {
+        Some(c) if allowed => Ok(c),
+        _ => Err(Diagnostic::error(Code::InvalidUniversalCharacter,
+                                   format!("\\U{:08X} is not a valid universal character", value))),
+    }
+}
+
+/// Decodes an escape sequence, just after the backslash.
+fn decode_escape<I: Iterator<char>>(it: &mut IteratorPeeker<char, I>) -> Result<Unit,Diagnostic>
+{
+    let c = match it.next() {
+        Some(c) => c,
+        None => { return Err(escape_error("expected an escape sequence after '\\'".to_string())); },
+    };
+    
+    match c {
+        // simple escape sequences.
+        '\'' | '"' | '?' | '\\' => Ok(Unit::Char(c)),
+        'a' => Ok(Unit::Char('\x07')),
+        'b' => Ok(Unit::Char('\x08')),
+        'f' => Ok(Unit::Char('\x0c')),
+        'n' => Ok(Unit::Char('\n')),
+        'r' => Ok(Unit::Char('\r')),
+        't' => Ok(Unit::Char('\t')),
+        'v' => Ok(Unit::Char('\x0b')),
+        
+        // octal escape sequences have up to three digits.
+        '0'...'7' => {
+            let mut value = c.to_digit(8).unwrap() as u32;
+            
+            for _ in range(0u, 2) {
+                match it.peek() {
+                    Some(d) if d.is_digit(8) => {
+                        it.eat();
+                        value = (value * 8) + (d.to_digit(8).unwrap() as u32);
+                    },
+                    _ => break,
+                }
+            }
+            
+            Ok(Unit::Raw(value))
+        },
+        
+        // hexadecimal escape sequences go on for as long as there are digits.
+        'x' => {
+            let mut value: u32 = 0;
+            let mut digit_count = 0u;
+            
+            loop {
+                match it.peek() {
+                    Some(d) if d.is_digit(16) => {
+                        it.eat();
+                        digit_count += 1;
+                        
+                        value = match value.checked_mul(16) {
+                            Some(v) => v + (d.to_digit(16).unwrap() as u32),
+                            None => { return Err(escape_error("hexadecimal escape sequence out of range".to_string())); },
+                        };
+                    },
+                    _ => break,
+                }
+            }
+            
+            match digit_count {
+                0 => Err(escape_error("\\x used with no following hex digits".to_string())),
+                _ => Ok(Unit::Raw(value)),
+            }
+        },
+        
+        // universal character names.
+        'u' => decode_universal(it, 4),
+        'U' => decode_universal(it, 8),
+        
+        c => Err(escape_error(format!("unknown escape sequence '\\{}'", c))),
+    }
+}
+
+/// Decodes the digits of a universal character name.
+fn decode_universal<I: Iterator<char>>(it: &mut IteratorPeeker<char, I>, digit_count: uint) -> Result<Unit,Diagnostic>
+{
+    let mut value: u32 = 0;
+    
+    for _ in range(0, digit_count) {
+        match it.next() {
+            Some(d) if d.is_digit(16) => {
+                value = (value * 16) + (d.to_digit(16).unwrap() as u32);
+            },
+            _ => {
+                return Err(Diagnostic::error(Code::InvalidUniversalCharacter,
+                                             format!("incomplete universal character name, expected {} hex digits", digit_count)));
+            },
+        }
+    }
+    
+    universal_character(value).map(|c| Unit::Char(c))
+}
+
+fn escape_error(message: String) -> Diagnostic
+{
+    Diagnostic::error(Code::InvalidEscapeSequence, message)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use super::{decode,Encoding,Unit};
+    use diagnostic::Code;
+    
+    fn code_units(body: &str, encoding: Encoding) -> Vec<u32>
+    {
+        decode(body, encoding).unwrap().code_units
+    }
+    
+    fn error_code(body: &str) -> Code
+    {
+        decode(body, Encoding::Plain).unwrap_err().code
+    }
+    
+    #[test]
+    fn simple_escapes()
+    {
+        assert_eq!(code_units("a\\n\\t\\\\\\\"\\'\\?", Encoding::Plain), vec![0x61, 0x0A, 0x09, 0x5C, 0x22, 0x27, 0x3F]);
+        assert_eq!(code_units("\\a\\b\\f\\r\\v", Encoding::Plain), vec![0x07, 0x08, 0x0C, 0x0D, 0x0B]);
+        assert_eq!(error_code("\\q"), Code::InvalidEscapeSequence);
+        assert_eq!(error_code("\\"), Code::InvalidEscapeSequence);
+    }
+    
+    #[test]
+    fn octal_and_hexadecimal_escapes()
+    {
+        // octal escapes stop after three digits, and hexadecimal ones at the first non-digit.
+        assert_eq!(code_units("\\0\\101\\1234", Encoding::Plain), vec![0x00, 0x41, 0x53, 0x34]);
+        assert_eq!(code_units("\\x41g\\x00041", Encoding::Plain), vec![0x41, 0x67, 0x41]);
+        assert_eq!(decode("\\x41", Encoding::Plain).unwrap().units, vec![Unit::Raw(0x41)]);
+        
+        assert_eq!(error_code("\\x"), Code::InvalidEscapeSequence);
+        assert_eq!(error_code("\\x100000000"), Code::InvalidEscapeSequence);
+        
+        // raw values have to fit in a single code unit.
+        assert_eq!(error_code("\\x100"), Code::CharacterOutOfRange);
+        assert_eq!(code_units("\\x100", Encoding::Utf16), vec![0x100]);
+        assert_eq!(code_units("\\xFFFFFFFF", Encoding::Wide), vec![0xFFFFFFFF]);
+    }
+    
+    #[test]
+    fn universal_characters()
+    {
+        assert_eq!(decode("\\u00E9", Encoding::Plain).unwrap().units, vec![Unit::Char('é')]);
+        assert_eq!(code_units("\\u0024\\u0040\\u0060", Encoding::Plain), vec![0x24, 0x40, 0x60]);
+        
+        // characters are encoded in the encoding of the literal.
+        assert_eq!(code_units("\\u00E9", Encoding::Plain), vec![0xC3, 0xA9]);
+        assert_eq!(code_units("\\U0001F600", Encoding::Utf8), vec![0xF0, 0x9F, 0x98, 0x80]);
+        assert_eq!(code_units("\\U0001F600", Encoding::Utf16), vec![0xD83D, 0xDE00]);
+        assert_eq!(code_units("\\U0001F600", Encoding::Utf32), vec![0x1F600]);
+        assert_eq!(code_units("é", Encoding::Utf16), vec![0xE9]);
+        
+        // the basic character set, surrogates and short names aren't allowed.
+        assert_eq!(error_code("\\u0041"), Code::InvalidUniversalCharacter);
+        assert_eq!(error_code("\\uD800"), Code::InvalidUniversalCharacter);
+        assert_eq!(error_code("\\U00110000"), Code::InvalidUniversalCharacter);
+        assert_eq!(error_code("\\u12"), Code::InvalidUniversalCharacter);
+    }
+}
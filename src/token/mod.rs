@@ -0,0 +1,465 @@
+
+use std;
+use std::string::CowString;
+
+use location::Span;
+use diagnostic::Diagnostic;
+
+pub use self::tokenizer::Tokenizer;
+pub use self::literal::Literal;
+pub use self::punctuator::Punctuator;
+pub use self::keyword::Keyword;
+pub use self::trivia::Trivia;
+
+pub mod tokenizer;
+pub mod literal;
+pub mod numeric;
+pub mod punctuator;
+pub mod keyword;
+pub mod phases;
+pub mod trivia;
+
+/// The type of a token.
+#[deriving(Clone,PartialEq)]
+pub enum Kind
+{
+    Punctuator(Punctuator),
+    Word,
+    /// A keyword of the language standard in use.
+    /// The token value still holds the spelling, so it can be treated as a word.
+    Keyword(Keyword),
+    /// A preprocessing number, such as '0x1p-3' or '1.2.3e+xy'.
+    /// These are turned into integer or floating literals by `Token::classify`
+    /// once preprocessing is done, as they don't need to make sense until then.
+    PpNumber,
+    IntegerLiteral(numeric::Integer),
+    FloatLiteral(numeric::Float),
+    /// A string literal, along with its decoded value.
+    StringLiteral(Literal),
+    /// A character constant, along with its decoded value.
+    CharLiteral(Literal),
+    /// The name of a header in an '#include' directive or '__has_include' expression.
+    /// The token value still holds the delimiters.
+    HeaderName(HeaderDelimiter),
+    /// A '//' comment, including the slashes.
+    /// Comments are only given as tokens if the tokenizer is asked to.
+    LineComment,
+    /// A '/* */' comment, including the delimiters.
+    BlockComment,
+    /// The new line (or end of file) which ends a preprocessing directive.
+    /// New lines outside of directives are just whitespace.
+    EndOfDirective,
+    /// The end of the source, which holds any trivia after the last token.
+    /// This is only given when trivia is being kept.
+    EndOfFile,
+    /// Source which couldn't be tokenized, along with what went wrong.
+    /// These are only given by a tokenizer which is recovering from errors.
+    Error(Diagnostic),
+}
+
+impl std::fmt::Show for Kind
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(), std::fmt::Error>
+    {
+        match self {
+            &Kind::Punctuator(..) => "punctuator",
+            &Kind::Word => "word",
+            &Kind::Keyword(..) => "keyword",
+            &Kind::PpNumber => "number",
+            &Kind::IntegerLiteral(..) => "integer",
+            &Kind::FloatLiteral(..) => "floating constant",
+            &Kind::StringLiteral(..) => "string",
+            &Kind::CharLiteral(..) => "character",
+            &Kind::HeaderName(..) => "header name",
+            &Kind::LineComment | &Kind::BlockComment => "comment",
+            &Kind::EndOfDirective => "end of directive",
+            &Kind::EndOfFile => "end of file",
+            &Kind::Error(..) => "error",
+        }.fmt(formatter)
+    }
+}
+
+/// How the name of a header is written.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum HeaderDelimiter
+{
+    /// `<stdio.h>`, which is looked for in the include paths and then the system include paths,
+    /// but not next to the including file.
+    AngleBrackets,
+    /// `"header.h"`, which is looked for next to the including file first.
+    Quotes,
+}
+
+/// A token.
+#[deriving(Clone)]
+pub struct Token
+{
+    pub kind: Kind,
+    pub value: String,
+    
+    /// The region of source the token was read from.
+    pub span: Span,
+    /// The whitespace and comments around the token.
+    /// This is only kept if the tokenizer was asked for it.
+    pub trivia: Option<Trivia>,
+    /// Whether the token is the first on its line.
+    pub line_start: bool,
+    /// Whether there is whitespace (or a comment) before the token.
+    pub leading_space: bool,
+}
+
+impl Token
+{
+    /// Creates a new token.
+    pub fn new(kind: Kind, value: String, span: Span) -> Token
+    {
+        Token {
+            kind: kind,
+            value: value,
+            span: span,
+            trivia: None,
+            line_start: false,
+            leading_space: false,
+        }
+    }
+    
+    /// Creates a punctuator token which does not come from any source.
+    pub fn punctuator(punctuator: Punctuator) -> Token
+    {
+        Token::new(Kind::Punctuator(punctuator), punctuator.spelling().to_string(), Span::dummy())
+    }
+    
+    // Special characters.
+    pub fn end_of_directive() -> Token { Token::new(Kind::EndOfDirective, "\n".to_string(), Span::dummy()) }
+    
+    // Punctuators.
+    pub fn left_parenthesis() -> Token { Token::punctuator(Punctuator::LeftParenthesis) }
+    pub fn right_parenthesis() -> Token { Token::punctuator(Punctuator::RightParenthesis) }
+    pub fn hash() -> Token { Token::punctuator(Punctuator::Hash) }
+    pub fn comma() -> Token { Token::punctuator(Punctuator::Comma) }
+    pub fn semicolon() -> Token { Token::punctuator(Punctuator::Semicolon) }
+    pub fn forward_slash() -> Token { Token::punctuator(Punctuator::ForwardSlash) }
+    pub fn asterix() -> Token { Token::punctuator(Punctuator::Asterix) }
+    
+    // Keywords.
+    pub fn define() -> Token { Token::new(Kind::Word, "define".to_string(), Span::dummy()) }
+    
+    pub fn move_value(self) -> String
+    {
+        self.value
+    }
+    
+    pub fn value<'a>(&'a self) -> &'a str
+    {
+        self.value.as_slice()
+    }
+    
+    /// Gets the text of the token exactly as it was written, along with its trivia.
+    /// Gives nothing if the tokenizer wasn't keeping trivia.
+    pub fn source_text(&self) -> Option<String>
+    {
+        self.trivia.as_ref().map(|trivia| trivia.source_text())
+    }
+    
+    /// Turns a preprocessing number into an integer or floating literal.
+    /// Any other token is given back as it is.
+    pub fn classify(self) -> Result<Token,Diagnostic>
+    {
+        match self.kind {
+            Kind::PpNumber => match numeric::parse(self.value.as_slice()) {
+                Ok(kind) => Ok(Token {
+                    kind: kind,
+                    .. self
+                }),
+                Err(diagnostic) => Err(diagnostic.with_span(self.span)),
+            },
+            _ => Ok(self),
+        }
+    }
+
+    /// Checks if the token is of a given kind.
+    pub fn is(&self, kind: Kind) -> bool
+    {
+        self.kind == kind
+    }
+    
+    /// Checks if the token is an identifier.
+    /// Keywords count, as the preprocessor doesn't know about them.
+    pub fn is_identifier(&self) -> bool
+    {
+        match self.kind {
+            Kind::Word | Kind::Keyword(..) => true,
+            _ => false,
+        }
+    }
+    
+    /// Checks if the token is a given punctuator.
+    pub fn is_punctuator(&self, punctuator: Punctuator) -> bool
+    {
+        self.kind == Kind::Punctuator(punctuator)
+    }
+}
+
+/// Tokens are compared by kind and value only, so that a token read from
+/// a file is equal to the same token built by hand.
+/// Punctuators and ends of directives are compared by kind alone, so that digraphs
+/// are equal to the punctuator they stand for.
+impl PartialEq for Token
+{
+    fn eq(&self, other: &Token) -> bool
+    {
+        match (&self.kind, &other.kind) {
+            (&Kind::Punctuator(..), &Kind::Punctuator(..)) => self.kind == other.kind,
+            (&Kind::EndOfDirective, &Kind::EndOfDirective) => true,
+            _ => (self.kind == other.kind) && (self.value == other.value),
+        }
+    }
+}
+
+impl Eq for Token { }
+
+impl std::fmt::Show for Token
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(),std::fmt::Error>
+    {
+        self.value.fmt(formatter)
+    }
+}
+
+/// A token which borrows its spelling from the source text.
+#[deriving(Clone,Show)]
+pub struct Lexeme<'a>
+{
+    pub kind: Kind,
+    /// The spelling of the token.
+    /// This is only owned if the token was written across a line splice or with a trigraph.
+    pub spelling: CowString<'a>,
+    pub span: Span,
+    /// The whitespace and comments around the token, if they are being kept.
+    pub trivia: Option<Trivia>,
+    /// Whether the lexeme is the first on its line.
+    pub line_start: bool,
+    /// Whether there is whitespace (or a comment) before the lexeme.
+    pub leading_space: bool,
+}
+
+impl<'a> Lexeme<'a>
+{
+    /// Converts the lexeme into a token which owns its spelling.
+    pub fn into_token(self) -> Token
+    {
+        let mut token = Token::new(self.kind, self.spelling.into_owned(), self.span);
+        token.trivia = self.trivia;
+        token.line_start = self.line_start;
+        token.leading_space = self.leading_space;
+        
+        token
+    }
+}
+
+/// A collection of internal methods for token checking.
+#[allow(dead_code)]
+pub mod expect
+{
+    use token::{Token,Kind};
+    use diagnostic::{Diagnostic,Code};
+    use util;
+    
+    /// Checks that there is a token.
+    pub fn something(opt: Option<Result<Token,Diagnostic>>) -> Result<Token,Diagnostic>
+    {
+        match opt {
+            Some(thing) => thing,
+            None => Err(self::unexpected(None, "expected a token".to_string())),
+        }
+    }
+    
+    /// Asserts that there is a token.
+    pub fn assert_something(opt: Option<Result<Token,Diagnostic>>) -> Token
+    {
+        self::assert_result(self::something(opt))
+    }
+    
+    /// Checks that a token is equal to a given token.
+    pub fn token(opt: Option<Result<Token,Diagnostic>>, expected_token: &Token) -> Result<Token,Diagnostic>
+    {
+        // the token we read, if it wasn't what we wanted.
+        let mut found = None;
+        
+        match opt {
+            Some(Ok(read_token)) => {
+                if &read_token == expected_token {
+                    return Ok(read_token);
+                }
+                
+                found = Some(read_token);
+            },
+            Some(Err(err)) => {
+                return Err(err);
+            },
+            None => (),
+        }
+        
+        let msg = format!("expected {}", expected_token);
+        Err(self::unexpected(found, msg))
+    }
+    
+    /// Asserts that a token is equal to a given token.
+    pub fn assert_token(opt: Option<Result<Token,Diagnostic>>, expected_token: &Token) -> Token
+    {
+        self::assert_result(self::token(opt, expected_token))
+    }
+    
+    /// Checks that a token is of a given kind.
+    pub fn kind(opt: Option<Result<Token,Diagnostic>>, expected_kind: Kind) -> Result<Token,Diagnostic>
+    {
+        // the token we read, if it wasn't what we wanted.
+        let mut found = None;
+        
+        match opt {
+            Some(Ok(read_token)) => {
+                if read_token.is(expected_kind) {
+                    return Ok(read_token);
+                }
+                
+                found = Some(read_token);
+            },
+            Some(Err(err)) => {
+                return Err(err);
+            },
+            None => (),
+        }
+        
+        let msg = format!("expected {}", expected_kind);
+        Err(self::unexpected(found, msg))
+    }
+    
+    /// Checks that a token is of a given kind.
+    pub fn assert_kind(opt: Option<Result<Token,Diagnostic>>, expected_kind: Kind) -> Token
+    {
+        self::assert_result(self::kind(opt, expected_kind))
+    }
+    
+    /// Checks that a token is an identifier, counting keywords.
+    pub fn identifier(opt: Option<Result<Token,Diagnostic>>) -> Result<Token,Diagnostic>
+    {
+        let mut found = None;
+        
+        match opt {
+            Some(Ok(read_token)) => {
+                if read_token.is_identifier() {
+                    return Ok(read_token);
+                }
+                
+                found = Some(read_token);
+            },
+            Some(Err(err)) => {
+                return Err(err);
+            },
+            None => (),
+        }
+        
+        Err(self::unexpected(found, "expected an identifier".to_string()))
+    }
+    
+    /// Checks that a token is an element of a set of kinds.
+    pub fn kinds<I: Iterator<Kind>>(opt: Option<Result<Token,Diagnostic>>, mut expected_kinds: I) -> Result<Token,Diagnostic>
+    {
+        // a list of kinds collected from the expected kind iterator.
+        // if the token is not matched, this list will contain all expected kinds.
+        let mut kind_list = Vec::new();
+        let mut found = None;
+        
+        match opt {
+            Some(Ok(read_token)) => {
+                for expected_kind in expected_kinds {
+                
+                    kind_list.push(expected_kind.clone());
+                    
+                    if read_token.is(expected_kind) {
+                        return Ok(read_token);
+                    }
+                }
+                
+                found = Some(read_token);
+            },
+            Some(Err(err)) => {
+                return Err(err);
+            },
+            None => (),
+        }
+        
+        let msg = format!("expected one of: {}", kind_list);
+        Err(self::unexpected(found, msg))
+    }
+    
+    /// Asserts that a token is an element of a set of kinds.
+    pub fn assert_kinds<I: Iterator<Kind>>(opt: Option<Result<Token,Diagnostic>>, expected_kinds: I) -> Token
+    {
+        self::assert_result(self::kinds(opt, expected_kinds))
+    }
+    
+    /// Checks that the next token is one of a set of tokens.
+    pub fn one_of<'a, I: Iterator<&'a Token>>(opt: Option<Result<Token,Diagnostic>>, mut expected_tokens: I) -> Result<Token,Diagnostic>
+    {
+        // a list of tokens collected from the expected token iterator.
+        // if the token is not matched, this list will contain all expected tokens.
+        let mut token_list = Vec::new();
+        let mut found = None;
+        
+        match opt {
+            Some(Ok(read_token)) => {
+                
+                for possible_token in expected_tokens {
+                    token_list.push(possible_token.clone());
+                    
+                    if &read_token == possible_token {
+                        return Ok(read_token);
+                    }
+                }
+                
+                found = Some(read_token);
+            },
+            Some(Err(err)) => {
+                return Err(err);
+            },
+            None => (),
+        };
+        
+        let msg = format!("expected one of: {}", util::build_list_str(token_list.iter()));
+        Err(self::unexpected(found, msg))
+    }
+    
+    /// Asserts that the next token is one of a set of tokens.
+    pub fn assert_one_of<'a, I: Iterator<&'a Token>>(opt: Option<Result<Token,Diagnostic>>, expected_tokens: I) -> Token
+    {
+        match self::one_of(opt, expected_tokens) {
+            Ok(token) => token,
+            Err(err) => panic!("{}", err),
+        }
+    }
+    
+    /// Builds the error for when we found something other than what we expected.
+    fn unexpected(found: Option<Token>, msg: String) -> Diagnostic
+    {
+        match found {
+            Some(token) => {
+                Diagnostic::error(Code::UnexpectedToken, format!("{}, found '{}'", msg, token))
+                    .with_span(token.span)
+            },
+            None => {
+                Diagnostic::error(Code::UnexpectedEndOfFile, format!("{}, found end of file", msg))
+            },
+        }
+    }
+    
+    /// Helper method for unwrapping an expect result.
+    fn assert_result(opt: Result<Token,Diagnostic>) -> Token
+    {
+        match opt {
+            Ok(token) => token,
+            Err(err) => panic!("{}", err),
+        }
+    }
+        
+}
@@ -0,0 +1,412 @@
+
+use std;
+use std::num::Float;
+
+use token::Kind;
+use diagnostic::{Diagnostic,Code};
+
+/// The base a numeric literal is written in.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum Base
+{
+    Decimal,
+    Octal,
+    Hexadecimal,
+    Binary,
+}
+
+impl Base
+{
+    pub fn radix(&self) -> uint
+    {
+        match *self {
+            Base::Decimal => 10,
+            Base::Octal => 8,
+            Base::Hexadecimal => 16,
+            Base::Binary => 2,
+        }
+    }
+}
+
+/// The size given by the suffix of an integer constant.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum IntegerSize
+{
+    Int,
+    Long,
+    LongLong,
+}
+
+/// An integer constant.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub struct Integer
+{
+    pub value: u64,
+    pub base: Base,
+
+    /// Whether the constant has a `u` suffix.
+    pub unsigned: bool,
+    pub size: IntegerSize,
+}
+
+/// The type given by the suffix of a floating constant.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum FloatSize
+{
+    Float,
+    Double,
+    LongDouble,
+}
+
+/// A floating constant.
+#[deriving(Clone,PartialEq,Show)]
+pub struct Float
+{
+    pub value: f64,
+    pub base: Base,
+    pub size: FloatSize,
+}
+
+/// Parses the spelling of a numeric literal.
+/// Gives back either an integer or a floating literal kind.
+pub fn parse(spelling: &str) -> Result<Kind,Diagnostic>
+{
+    let chars: Vec<char> = spelling.chars().collect();
+    let mut pos = 0u;
+
+    let base = if has_prefix(chars.as_slice(), 'x') {
+        pos = 2;
+        Base::Hexadecimal
+    } else if has_prefix(chars.as_slice(), 'b') {
+        pos = 2;
+        Base::Binary
+    } else {
+        Base::Decimal
+    };
+
+    // decimal digits are read for binary literals so that we can complain about
+    // '2' in '0b12' rather than treating it as a suffix.
+    let digit_radix = match base {
+        Base::Hexadecimal => 16,
+        _ => 10,
+    };
+
+    let whole = try!(read_digits(chars.as_slice(), &mut pos, digit_radix));
+
+    let is_float = match chars.as_slice().get(pos) {
+        Some(&'.') => true,
+        Some(&'e') | Some(&'E') => base == Base::Decimal,
+        Some(&'p') | Some(&'P') => base == Base::Hexadecimal,
+        _ => false,
+    };
+
+    if is_float {
+        parse_float(chars.as_slice(), pos, base, whole)
+    } else {
+        parse_integer(chars.as_slice(), pos, base, whole)
+    }
+}
+
+fn parse_integer(chars: &[char], pos: uint, base: Base, digits: String) -> Result<Kind,Diagnostic>
+{
+    if digits.is_empty() {
+        return Err(error(format!("{} constant has no digits", describe_base(&base))));
+    }
+
+    // a leading zero makes the constant octal.
+    let base = match base {
+        Base::Decimal if digits.as_slice().starts_with("0") => Base::Octal,
+        base => base,
+    };
+
+    let mut value: u64 = 0;
+
+    for c in digits.as_slice().chars() {
+        let digit = match c.to_digit(base.radix()) {
+            Some(digit) => digit as u64,
+            None => { return Err(error(format!("invalid digit '{}' in {} constant", c, describe_base(&base)))); },
+        };
+
+        value = match value.checked_mul(base.radix() as u64).and_then(|v| v.checked_add(digit)) {
+            Some(value) => value,
+            None => { return Err(error("integer constant is too large for its type".to_string())); },
+        };
+    }
+
+    let suffix = String::from_chars(chars.slice_from(pos));
+
+    let (unsigned, size) = match suffix.as_slice() {
+        "" => (false, IntegerSize::Int),
+        "u" | "U" => (true, IntegerSize::Int),
+        "l" | "L" => (false, IntegerSize::Long),
+        "ul" | "uL" | "Ul" | "UL" |
+        "lu" | "lU" | "Lu" | "LU" => (true, IntegerSize::Long),
+        "ll" | "LL" => (false, IntegerSize::LongLong),
+        "ull" | "uLL" | "Ull" | "ULL" |
+        "llu" | "llU" | "LLu" | "LLU" => (true, IntegerSize::LongLong),
+        s => { return Err(error(format!("invalid suffix '{}' on integer constant", s))); },
+    };
+
+    Ok(Kind::IntegerLiteral(Integer {
+        value: value,
+        base: base,
+        unsigned: unsigned,
+        size: size,
+    }))
+}
+
+fn parse_float(chars: &[char], mut pos: uint, base: Base, whole: String) -> Result<Kind,Diagnostic>
+{
+    if base == Base::Binary {
+        return Err(error("binary floating constants are not allowed".to_string()));
+    }
+
+    let radix = base.radix();
+
+    let fraction = match chars.get(pos) {
+        Some(&'.') => {
+            pos += 1;
+            try!(read_digits(chars, &mut pos, radix))
+        },
+        _ => String::new(),
+    };
+
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(error(format!("{} floating constant has no digits", describe_base(&base))));
+    }
+
+    let exponent = match chars.get(pos) {
+        Some(&'e') | Some(&'E') if base == Base::Decimal => {
+            pos += 1;
+            Some(try!(read_exponent(chars, &mut pos)))
+        },
+        Some(&'p') | Some(&'P') if base == Base::Hexadecimal => {
+            pos += 1;
+            Some(try!(read_exponent(chars, &mut pos)))
+        },
+        _ => None,
+    };
+
+    let suffix = String::from_chars(chars.slice_from(pos));
+
+    let size = match suffix.as_slice() {
+        "" => FloatSize::Double,
+        "f" | "F" => FloatSize::Float,
+        "l" | "L" => FloatSize::LongDouble,
+        s => { return Err(error(format!("invalid suffix '{}' on floating constant", s))); },
+    };
+
+    let value = match base {
+        Base::Hexadecimal => {
+            let exponent = match exponent {
+                Some(exponent) => exponent,
+                None => { return Err(error("hexadecimal floating constant requires an exponent".to_string())); },
+            };
+
+            // the mantissa is read as a whole number, and the exponent adjusted for
+            // the number of digits after the point.
+            let mut mantissa = 0f64;
+
+            for c in whole.as_slice().chars().chain(fraction.as_slice().chars()) {
+                mantissa = (mantissa * 16.0) + (c.to_digit(16).unwrap() as f64);
+            }
+
+            let shift = exponent - ((fraction.len() * 4) as i64);
+            mantissa * 2f64.powi(shift as i32)
+        },
+        _ => {
+            let text = format!("{}.{}e{}",
+                               if whole.is_empty() { "0" } else { whole.as_slice() },
+                               if fraction.is_empty() { "0" } else { fraction.as_slice() },
+                               exponent.unwrap_or(0));
+
+            match std::str::from_str::<f64>(text.as_slice()) {
+                Some(value) => value,
+                None => { return Err(error("invalid floating constant".to_string())); },
+            }
+        },
+    };
+
+    Ok(Kind::FloatLiteral(Float {
+        value: value,
+        base: base,
+        size: size,
+    }))
+}
+
+/// Reads a run of digits, skipping over digit separators.
+fn read_digits(chars: &[char], pos: &mut uint, radix: uint) -> Result<String,Diagnostic>
+{
+    let mut digits = String::new();
+
+    loop {
+        match chars.get(*pos) {
+            Some(&c) if c.is_digit(radix) => {
+                digits.push(c);
+                *pos += 1;
+            },
+            Some(&'\'') => {
+                // a separator must come between two digits.
+                let next_is_digit = match chars.get(*pos + 1) {
+                    Some(&c) => c.is_digit(radix),
+                    None => false,
+                };
+
+                if digits.is_empty() || !next_is_digit {
+                    return Err(error("digit separator must appear between digits".to_string()));
+                }
+
+                *pos += 1;
+            },
+            _ => break,
+        }
+    }
+
+    Ok(digits)
+}
+
+/// Reads the exponent of a floating constant, just after the 'e' or 'p'.
+fn read_exponent(chars: &[char], pos: &mut uint) -> Result<i64,Diagnostic>
+{
+    let negative = match chars.get(*pos) {
+        Some(&'+') => { *pos += 1; false },
+        Some(&'-') => { *pos += 1; true },
+        _ => false,
+    };
+
+    let digits = try!(read_digits(chars, pos, 10));
+
+    if digits.is_empty() {
+        return Err(error("exponent has no digits".to_string()));
+    }
+
+    let magnitude = match std::str::from_str::<i64>(digits.as_slice()) {
+        Some(magnitude) => magnitude,
+        None => { return Err(error("exponent is too large".to_string())); },
+    };
+
+    Ok(if negative { -magnitude } else { magnitude })
+}
+
+/// Checks whether a literal starts with '0' followed by the given letter, in either case.
+fn has_prefix(chars: &[char], letter: char) -> bool
+{
+    match (chars.get(0), chars.get(1)) {
+        (Some(&'0'), Some(&c)) => c.to_lowercase() == letter,
+        _ => false,
+    }
+}
+
+fn describe_base(base: &Base) -> &'static str
+{
+    match *base {
+        Base::Decimal => "decimal",
+        Base::Octal => "octal",
+        Base::Hexadecimal => "hexadecimal",
+        Base::Binary => "binary",
+    }
+}
+
+fn error(message: String) -> Diagnostic
+{
+    Diagnostic::error(Code::InvalidNumericLiteral, message)
+}
+
+#[cfg(test)]
+mod tests
+{
+    use token::Kind;
+    use super::{parse,Base,Integer,IntegerSize,Float,FloatSize};
+
+    fn integer(spelling: &str) -> Integer
+    {
+        match parse(spelling) {
+            Ok(Kind::IntegerLiteral(integer)) => integer,
+            other => panic!("'{}' gave {} rather than an integer", spelling, other),
+        }
+    }
+
+    fn float(spelling: &str) -> Float
+    {
+        match parse(spelling) {
+            Ok(Kind::FloatLiteral(float)) => float,
+            other => panic!("'{}' gave {} rather than a floating constant", spelling, other),
+        }
+    }
+
+    #[test]
+    fn integer_suffixes()
+    {
+        let cases = [
+            ("1", false, IntegerSize::Int),
+            ("1u", true, IntegerSize::Int),
+            ("1L", false, IntegerSize::Long),
+            ("1uL", true, IntegerSize::Long),
+            ("1Lu", true, IntegerSize::Long),
+            ("1ll", false, IntegerSize::LongLong),
+            ("1ULL", true, IntegerSize::LongLong),
+            ("1llu", true, IntegerSize::LongLong),
+        ];
+
+        for &(spelling, unsigned, ref size) in cases.iter() {
+            let integer = integer(spelling);
+
+            assert_eq!(integer.value, 1);
+            assert_eq!(integer.unsigned, unsigned);
+            assert_eq!(&integer.size, size);
+        }
+
+        for spelling in ["1lL", "1uu", "1lul", "1f", "1x"].iter() {
+            assert!(parse(*spelling).is_err(), "'{}' should be rejected", spelling);
+        }
+    }
+
+    #[test]
+    fn integer_bases_and_ranges()
+    {
+        assert_eq!(integer("0755").value, 493);
+        assert_eq!(integer("0755").base, Base::Octal);
+        assert_eq!(integer("0").base, Base::Octal);
+        assert_eq!(integer("0x1F").value, 31);
+        assert_eq!(integer("0b101").value, 5);
+        assert_eq!(integer("0b101").base, Base::Binary);
+
+        assert_eq!(integer("18446744073709551615").value, 18446744073709551615);
+        assert_eq!(integer("0xFFFFFFFFFFFFFFFF").value, 0xFFFFFFFFFFFFFFFF);
+
+        for spelling in ["18446744073709551616", "0x10000000000000000", "08", "0b12", "0x", "0b"].iter() {
+            assert!(parse(*spelling).is_err(), "'{}' should be rejected", spelling);
+        }
+    }
+
+    #[test]
+    fn digit_separators()
+    {
+        assert_eq!(integer("1'000'000").value, 1000000);
+        assert_eq!(integer("0x7F'FF").value, 0x7FFF);
+        assert_eq!(integer("0b1'0").value, 2);
+        assert_eq!(float("1'0.2'5").value, 10.25);
+
+        for spelling in ["1''0", "1'", "0x'1", "1'.5"].iter() {
+            assert!(parse(*spelling).is_err(), "'{}' should be rejected", spelling);
+        }
+    }
+
+    #[test]
+    fn floating_constants()
+    {
+        assert_eq!(float("1.5").value, 1.5);
+        assert_eq!(float("1.5").size, FloatSize::Double);
+        assert_eq!(float(".5f").size, FloatSize::Float);
+        assert_eq!(float("1.L").size, FloatSize::LongDouble);
+        assert_eq!(float("1e3").value, 1000.0);
+        assert_eq!(float("2.5E-1").value, 0.25);
+        assert_eq!(float("0x1p-2").value, 0.25);
+        assert_eq!(float("0x1.8p1").value, 3.0);
+        assert_eq!(float("0x.8P+0").base, Base::Hexadecimal);
+
+        for spelling in ["0x1.8", "1.0q", "0b1.0", "1e", "1e+", "."].iter() {
+            assert!(parse(*spelling).is_err(), "'{}' should be rejected", spelling);
+        }
+    }
+}
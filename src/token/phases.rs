@@ -0,0 +1,155 @@
+
+//! Translation phases 1 and 2, done lazily over borrowed source text.
+//!
+//! Trigraphs are replaced (if enabled), and backslash-newline sequences are
+//! skipped so that a line ending in a backslash is joined with the next.
+//! Offsets always refer to the original text, so tokens still point at the
+//! physical lines they were written on.
+
+/// Reads the character at a byte offset, replacing trigraphs.
+/// Gives back the character along with the offset just past it.
+pub fn raw_char_at(src: &str, offset: uint, trigraphs: bool) -> Option<(char, uint)>
+{
+    if offset >= src.len() {
+        return None;
+    }
+
+    if trigraphs && src.slice_from(offset).starts_with("??") {
+        match src.as_bytes().get(offset + 2).and_then(|&b| trigraph(b as char)) {
+            Some(c) => { return Some((c, offset + 3)); },
+            None => (),
+        }
+    }
+
+    let range = src.char_range_at(offset);
+    Some((range.ch, range.next))
+}
+
+/// Reads the character at a byte offset after translation phases 1 and 2.
+/// Any line splices before the character are skipped.
+/// Gives back the character along with the offset just past it.
+pub fn char_at(src: &str, mut offset: uint, trigraphs: bool) -> Option<(char, uint)>
+{
+    loop {
+        match splice_at(src, offset, trigraphs) {
+            Some(next) => { offset = next; },
+            None => { return raw_char_at(src, offset, trigraphs); },
+        }
+    }
+}
+
+/// Gets the offset just past a line splice, if one starts at the given offset.
+pub fn splice_at(src: &str, offset: uint, trigraphs: bool) -> Option<uint>
+{
+    match raw_char_at(src, offset, trigraphs) {
+        Some(('\\', next)) => new_line_at(src, next),
+        _ => None,
+    }
+}
+
+/// Gets the offset just past a new line, if one starts at the given offset.
+pub fn new_line_at(src: &str, offset: uint) -> Option<uint>
+{
+    let bytes = src.as_bytes();
+
+    match (bytes.get(offset), bytes.get(offset + 1)) {
+        (Some(&b'\n'), _) => Some(offset + 1),
+        (Some(&b'\r'), Some(&b'\n')) => Some(offset + 2),
+        _ => None,
+    }
+}
+
+/// Removes line splices and trigraphs from a piece of source.
+pub fn clean(src: &str, trigraphs: bool) -> String
+{
+    let mut result = String::new();
+    let mut offset = 0u;
+
+    loop {
+        match char_at(src, offset, trigraphs) {
+            Some((c, next)) => {
+                result.push(c);
+                offset = next;
+            },
+            None => break,
+        }
+    }
+
+    result
+}
+
+/// Gets the character a trigraph stands for, given the character after the '??'.
+fn trigraph(c: char) -> Option<char>
+{
+    match c {
+        '=' => Some('#'),
+        '(' => Some('['),
+        '/' => Some('\\'),
+        ')' => Some(']'),
+        '\'' => Some('^'),
+        '<' => Some('{'),
+        '!' => Some('|'),
+        '>' => Some('}'),
+        '-' => Some('~'),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use token::Tokenizer;
+    use token::tokenizer::Options;
+    use super::{char_at,clean,splice_at};
+
+    #[test]
+    fn trigraphs()
+    {
+        assert_eq!(clean("??=??(??/??)??'??<??!??>??-", true).as_slice(), "#[\\]^{|}~");
+        assert_eq!(clean("??=", false).as_slice(), "??=");
+
+        // only the last two '?' of '???=' make up the trigraph.
+        assert_eq!(clean("???=", true).as_slice(), "?#");
+        assert_eq!(clean("??a", true).as_slice(), "??a");
+    }
+
+    #[test]
+    fn line_splices()
+    {
+        assert_eq!(clean("a\\\nb\\\r\nc", false).as_slice(), "abc");
+        assert_eq!(clean("a\\\\\n\nb", false).as_slice(), "a\\\nb");
+        assert_eq!(clean("a\\ \nb", false).as_slice(), "a\\ \nb");
+
+        // '??/' is a backslash, so it can splice lines too.
+        assert_eq!(clean("a??/\nb", true).as_slice(), "ab");
+        assert_eq!(clean("a??/\nb", false).as_slice(), "a??/\nb");
+    }
+
+    #[test]
+    fn offsets()
+    {
+        // offsets are into the original text, past any splices.
+        assert_eq!(char_at("a\\\n\\\nb", 1, false), Some(('b', 6)));
+        assert_eq!(char_at("??=x", 0, true), Some(('#', 3)));
+        assert_eq!(char_at("a\\\n", 1, false), None);
+        assert_eq!(splice_at("\\\r\nb", 0, false), Some(3));
+        assert_eq!(splice_at("\\b", 0, false), None);
+    }
+
+    #[test]
+    fn tokens_keep_physical_positions()
+    {
+        let options = Options {
+            trigraphs: true,
+            .. Options::new()
+        };
+
+        let tokens: Vec<_> = Tokenizer::with_options("in\\\nt ??=", options).map(|token| token.unwrap()).collect();
+
+        assert_eq!(tokens.len(), 2);
+        assert_eq!(tokens[0].value.as_slice(), "int");
+        assert_eq!((tokens[0].span.start.line, tokens[0].span.end.line), (1, 2));
+        assert_eq!(tokens[1].value.as_slice(), "#");
+        assert_eq!((tokens[1].span.start.line, tokens[1].span.start.column), (2, 3));
+    }
+}
@@ -0,0 +1,316 @@
+
+use std;
+use std::rc::Rc;
+
+use standard::Standard;
+
+/// A punctuator.
+/// Digraphs are read as the punctuator they stand for.
+#[deriving(Clone,PartialEq,Eq,Hash)]
+pub enum Punctuator
+{
+    LeftBracket,
+    RightBracket,
+    LeftParenthesis,
+    RightParenthesis,
+    LeftBrace,
+    RightBrace,
+    Dot,
+    Arrow,
+
+    PlusPlus,
+    MinusMinus,
+    Ampersand,
+    Asterix,
+    Plus,
+    Minus,
+    Tilde,
+    Exclamation,
+
+    ForwardSlash,
+    Percent,
+    LeftShift,
+    RightShift,
+    Less,
+    Greater,
+    LessEqual,
+    GreaterEqual,
+    EqualEqual,
+    NotEqual,
+    Caret,
+    Pipe,
+    AmpersandAmpersand,
+    PipePipe,
+
+    Question,
+    Colon,
+    ColonColon,
+    Semicolon,
+    Ellipsis,
+
+    Equal,
+    AsterixEqual,
+    ForwardSlashEqual,
+    PercentEqual,
+    PlusEqual,
+    MinusEqual,
+    LeftShiftEqual,
+    RightShiftEqual,
+    AmpersandEqual,
+    CaretEqual,
+    PipeEqual,
+
+    Comma,
+    Hash,
+    HashHash,
+}
+
+/// Every spelling of every punctuator, including digraphs, along with the standard which introduced it.
+static SPELLINGS: &'static [(&'static str, Punctuator, Standard)] = &[
+    ("[", Punctuator::LeftBracket, Standard::C89),
+    ("]", Punctuator::RightBracket, Standard::C89),
+    ("(", Punctuator::LeftParenthesis, Standard::C89),
+    (")", Punctuator::RightParenthesis, Standard::C89),
+    ("{", Punctuator::LeftBrace, Standard::C89),
+    ("}", Punctuator::RightBrace, Standard::C89),
+    (".", Punctuator::Dot, Standard::C89),
+    ("->", Punctuator::Arrow, Standard::C89),
+
+    ("++", Punctuator::PlusPlus, Standard::C89),
+    ("--", Punctuator::MinusMinus, Standard::C89),
+    ("&", Punctuator::Ampersand, Standard::C89),
+    ("*", Punctuator::Asterix, Standard::C89),
+    ("+", Punctuator::Plus, Standard::C89),
+    ("-", Punctuator::Minus, Standard::C89),
+    ("~", Punctuator::Tilde, Standard::C89),
+    ("!", Punctuator::Exclamation, Standard::C89),
+
+    ("/", Punctuator::ForwardSlash, Standard::C89),
+    ("%", Punctuator::Percent, Standard::C89),
+    ("<<", Punctuator::LeftShift, Standard::C89),
+    (">>", Punctuator::RightShift, Standard::C89),
+    ("<", Punctuator::Less, Standard::C89),
+    (">", Punctuator::Greater, Standard::C89),
+    ("<=", Punctuator::LessEqual, Standard::C89),
+    (">=", Punctuator::GreaterEqual, Standard::C89),
+    ("==", Punctuator::EqualEqual, Standard::C89),
+    ("!=", Punctuator::NotEqual, Standard::C89),
+    ("^", Punctuator::Caret, Standard::C89),
+    ("|", Punctuator::Pipe, Standard::C89),
+    ("&&", Punctuator::AmpersandAmpersand, Standard::C89),
+    ("||", Punctuator::PipePipe, Standard::C89),
+
+    ("?", Punctuator::Question, Standard::C89),
+    (":", Punctuator::Colon, Standard::C89),
+    // before C23, 'a::b' is two ':' tokens.
+    ("::", Punctuator::ColonColon, Standard::C23),
+    (";", Punctuator::Semicolon, Standard::C89),
+    ("...", Punctuator::Ellipsis, Standard::C89),
+
+    ("=", Punctuator::Equal, Standard::C89),
+    ("*=", Punctuator::AsterixEqual, Standard::C89),
+    ("/=", Punctuator::ForwardSlashEqual, Standard::C89),
+    ("%=", Punctuator::PercentEqual, Standard::C89),
+    ("+=", Punctuator::PlusEqual, Standard::C89),
+    ("-=", Punctuator::MinusEqual, Standard::C89),
+    ("<<=", Punctuator::LeftShiftEqual, Standard::C89),
+    (">>=", Punctuator::RightShiftEqual, Standard::C89),
+    ("&=", Punctuator::AmpersandEqual, Standard::C89),
+    ("^=", Punctuator::CaretEqual, Standard::C89),
+    ("|=", Punctuator::PipeEqual, Standard::C89),
+
+    (",", Punctuator::Comma, Standard::C89),
+    ("#", Punctuator::Hash, Standard::C89),
+    ("##", Punctuator::HashHash, Standard::C89),
+
+    // digraphs.
+    ("<:", Punctuator::LeftBracket, Standard::C89),
+    (":>", Punctuator::RightBracket, Standard::C89),
+    ("<%", Punctuator::LeftBrace, Standard::C89),
+    ("%>", Punctuator::RightBrace, Standard::C89),
+    ("%:", Punctuator::Hash, Standard::C89),
+    ("%:%:", Punctuator::HashHash, Standard::C89),
+];
+
+impl Punctuator
+{
+    /// Gets the usual spelling of the punctuator.
+    pub fn spelling(&self) -> &'static str
+    {
+        // the first spelling in the table is never a digraph.
+        for &(spelling, ref punctuator, _) in SPELLINGS.iter() {
+            if punctuator == self {
+                return spelling;
+            }
+        }
+
+        unreachable!()
+    }
+}
+
+impl std::fmt::Show for Punctuator
+{
+    fn fmt(&self, formatter: &mut std::fmt::Formatter) -> Result<(),std::fmt::Error>
+    {
+        self.spelling().fmt(formatter)
+    }
+}
+
+/// A node in the punctuator trie.
+#[deriving(Clone)]
+struct Node
+{
+    /// The punctuator which ends at this node, if any, along with the standard which introduced it.
+    punctuator: Option<(Punctuator, Standard)>,
+    /// The character leading to each child node, along with the child's index.
+    children: Vec<(char, uint)>,
+}
+
+thread_local!(static TRIE: Rc<Trie> = Rc::new(Trie::new()))
+
+/// A trie of punctuator spellings, used to find the longest punctuator
+/// at the current position without trying every spelling in turn.
+#[deriving(Clone)]
+pub struct Trie
+{
+    nodes: Vec<Node>,
+}
+
+impl Trie
+{
+    /// Builds a trie containing the punctuators of every standard.
+    /// Tokenizers share a single trie through `Trie::shared`, rather than building their own.
+    pub fn new() -> Trie
+    {
+        let mut trie = Trie {
+            nodes: vec![ Node { punctuator: None, children: Vec::new() } ],
+        };
+
+        for &(spelling, ref punctuator, ref introduced) in SPELLINGS.iter() {
+            trie.insert(spelling, punctuator.clone(), introduced.clone());
+        }
+
+        trie
+    }
+
+    /// Gets the trie, which is only built once on each thread.
+    pub fn shared() -> Rc<Trie>
+    {
+        TRIE.with(|trie| trie.clone())
+    }
+
+    /// Finds the longest punctuator of a standard at the start of a character sequence.
+    /// `lookup` gives the n'th character from the current position.
+    /// Returns the punctuator and the number of characters it covers.
+    pub fn longest_match(&self, standard: &Standard, lookup: |uint| -> Option<char>) -> Option<(Punctuator, uint)>
+    {
+        let mut node = 0u;
+        let mut depth = 0u;
+        let mut longest = None;
+
+        loop {
+            let c = match lookup(depth) {
+                Some(c) => c,
+                None => break,
+            };
+
+            node = match self.child(node, c) {
+                Some(child) => child,
+                None => break,
+            };
+            depth += 1;
+
+            match self.nodes[node].punctuator {
+                Some((ref punctuator, ref introduced)) if introduced <= standard => {
+                    longest = Some((punctuator.clone(), depth));
+                },
+                _ => (),
+            }
+        }
+
+        longest
+    }
+
+    fn insert(&mut self, spelling: &str, punctuator: Punctuator, introduced: Standard)
+    {
+        let mut node = 0u;
+
+        for c in spelling.chars() {
+            node = match self.child(node, c) {
+                Some(child) => child,
+                None => {
+                    let child = self.nodes.len();
+                    self.nodes.push(Node { punctuator: None, children: Vec::new() });
+                    self.nodes[node].children.push((c, child));
+
+                    child
+                },
+            };
+        }
+
+        self.nodes[node].punctuator = Some((punctuator, introduced));
+    }
+
+    fn child(&self, node: uint, c: char) -> Option<uint>
+    {
+        for &(child_char, child) in self.nodes[node].children.iter() {
+            if child_char == c {
+                return Some(child);
+            }
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use standard::Standard;
+    use super::{Punctuator,Trie};
+
+    fn longest(standard: Standard, text: &str) -> Option<(Punctuator, uint)>
+    {
+        let chars: Vec<char> = text.chars().collect();
+        Trie::shared().longest_match(&standard, |n| chars.get(n).map(|c| *c))
+    }
+
+    #[test]
+    fn longest_match()
+    {
+        assert_eq!(longest(Standard::C17, "<<="), Some((Punctuator::LeftShiftEqual, 3)));
+        assert_eq!(longest(Standard::C17, "<<x"), Some((Punctuator::LeftShift, 2)));
+        assert_eq!(longest(Standard::C17, "<x"), Some((Punctuator::Less, 1)));
+        assert_eq!(longest(Standard::C17, "->>"), Some((Punctuator::Arrow, 2)));
+        assert_eq!(longest(Standard::C17, "..."), Some((Punctuator::Ellipsis, 3)));
+        assert_eq!(longest(Standard::C17, ".."), Some((Punctuator::Dot, 1)));
+        assert_eq!(longest(Standard::C17, "+++"), Some((Punctuator::PlusPlus, 2)));
+        assert_eq!(longest(Standard::C17, "a"), None);
+        assert_eq!(longest(Standard::C17, ""), None);
+    }
+
+    #[test]
+    fn digraphs()
+    {
+        assert_eq!(longest(Standard::C89, "<:"), Some((Punctuator::LeftBracket, 2)));
+        assert_eq!(longest(Standard::C89, ":>"), Some((Punctuator::RightBracket, 2)));
+        assert_eq!(longest(Standard::C89, "<%"), Some((Punctuator::LeftBrace, 2)));
+        assert_eq!(longest(Standard::C89, "%>"), Some((Punctuator::RightBrace, 2)));
+        assert_eq!(longest(Standard::C89, "%:%:"), Some((Punctuator::HashHash, 4)));
+        assert_eq!(longest(Standard::C89, "%:%"), Some((Punctuator::Hash, 2)));
+        assert_eq!(longest(Standard::C89, "%="), Some((Punctuator::PercentEqual, 2)));
+
+        // digraphs are spelled the usual way.
+        assert_eq!(Punctuator::LeftBracket.spelling(), "[");
+        assert_eq!(Punctuator::HashHash.spelling(), "##");
+    }
+
+    #[test]
+    fn colon_colon()
+    {
+        assert_eq!(longest(Standard::C17, "::"), Some((Punctuator::Colon, 1)));
+        assert_eq!(longest(Standard::C23, "::"), Some((Punctuator::ColonColon, 2)));
+        assert_eq!(longest(Standard::C23, ":::"), Some((Punctuator::ColonColon, 2)));
+        assert_eq!(longest(Standard::C17, "<::"), Some((Punctuator::LeftBracket, 2)));
+    }
+}
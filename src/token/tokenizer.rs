@@ -0,0 +1,1305 @@
+
+use identifier;
+
+use std;
+use std::borrow::Cow;
+use std::rc::Rc;
+use std::string::CowString;
+
+use token::{Kind,Token,Lexeme,Punctuator,HeaderDelimiter,literal};
+use token::{keyword,punctuator,phases,trivia};
+use token::trivia::Trivia;
+use location::{FileId,Position,Span};
+use standard::Standard;
+use diagnostic::{Diagnostic,Code};
+
+/// Settings which change how source is tokenized.
+#[deriving(Clone,Show)]
+pub struct Options
+{
+    /// The file being read.
+    pub file: FileId,
+    /// The language standard, which decides the set of keywords among other things.
+    pub standard: Standard,
+    /// Whether trigraphs such as '??=' are replaced.
+    pub trigraphs: bool,
+    /// Whether whitespace, comments and line splices are kept as trivia on each token,
+    /// so that the source can be rebuilt exactly from the tokens.
+    pub trivia: bool,
+    /// Whether errors are given back as error tokens, so that the rest
+    /// of the source can still be read.
+    pub recover: bool,
+    /// Whether comments are given as tokens, rather than being skipped like whitespace.
+    /// This has no effect if trivia is being kept, as comments are then trivia.
+    pub comments: bool,
+}
+
+impl Options
+{
+    /// Gets the default options.
+    pub fn new() -> Options
+    {
+        Options {
+            file: FileId(0),
+            standard: Standard::default(),
+            trigraphs: false,
+            trivia: false,
+            recover: false,
+            comments: false,
+        }
+    }
+}
+
+/// Whether new lines are significant.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum Mode
+{
+    /// Ordinary code, where new lines are just whitespace.
+    Code,
+    /// A preprocessing directive, which is ended by the next new line.
+    Directive,
+}
+
+/// Where we are in a line, as far as header names are concerned.
+#[deriving(Clone,PartialEq,Eq)]
+enum Context
+{
+    /// Just after a '#' at the start of a line.
+    Directive,
+    /// Just after '__has_include'.
+    HasInclude,
+    /// Where a header name may be written.
+    HeaderName,
+    Other,
+}
+
+/// A tokenizer.
+///
+/// The tokenizer works directly on the source text, so reading a token
+/// doesn't copy anything until an owned `Token` is asked for.
+#[deriving(Clone)]
+pub struct Tokenizer<'a>
+{
+    src: &'a str,
+    stack: Vec<Token>,
+    finished: bool,
+
+    options: Options,
+    mode: Mode,
+    /// Whether nothing but whitespace has been read since the last new line.
+    line_start: bool,
+    /// Whether the last lexeme was a comment, which counts as a space before the next.
+    after_comment: bool,
+    /// The position just past the last character we consumed.
+    pos: Position,
+    /// Whether the token being read crosses a line splice or trigraph,
+    /// meaning its spelling differs from the source text.
+    dirty: bool,
+    /// Warnings about tokens which were read anyway, waiting to be taken.
+    warnings: Vec<Diagnostic>,
+
+    // the possible punctuators, which are shared between tokenizers.
+    punctuators: Rc<punctuator::Trie>,
+    // the keywords, which are shared between tokenizers.
+    keywords: Rc<keyword::Table>,
+    // whether the next token may be a header name.
+    context: Context,
+}
+
+impl<'a> Tokenizer<'a>
+{
+    /// Creates a new tokenizer.
+    pub fn new(src: &'a str) -> Tokenizer<'a>
+    {
+        Tokenizer::with_options(src, Options::new())
+    }
+
+    /// Creates a new tokenizer with the given options.
+    pub fn with_options(src: &'a str, options: Options) -> Tokenizer<'a>
+    {
+        Tokenizer {
+            src: src,
+            stack: Vec::new(),
+            finished: false,
+
+            options: options,
+            mode: Mode::Code,
+            line_start: true,
+            after_comment: false,
+            pos: Position::start(),
+            dirty: false,
+            warnings: Vec::new(),
+
+            punctuators: punctuator::Trie::shared(),
+            keywords: keyword::Table::shared(),
+            context: Context::Other,
+        }
+    }
+
+    /// Turns the tokenizer into an iterator over lexemes, which borrow
+    /// their text from the source rather than copying it.
+    pub fn lexemes(self) -> Lexemes<'a>
+    {
+        assert!(self.stack.is_empty(), "tokens have already been peeked");
+
+        Lexemes {
+            tokenizer: self,
+        }
+    }
+
+    /// Gets the language standard being used.
+    pub fn standard(&self) -> Standard
+    {
+        self.options.standard.clone()
+    }
+
+    /// Gets the options the tokenizer was created with.
+    pub fn options(&self) -> Options
+    {
+        self.options.clone()
+    }
+
+    /// Gets whether new lines are being given as tokens.
+    pub fn mode(&self) -> Mode
+    {
+        self.mode.clone()
+    }
+
+    /// Sets whether new lines are being given as tokens.
+    /// Once a directive is started, the next new line is given as an end of
+    /// directive token and we go back to reading code.
+    /// Tokens which have already been peeked are not affected.
+    pub fn set_mode(&mut self, mode: Mode)
+    {
+        self.mode = mode;
+    }
+
+    /// Sets whether comments are given as tokens.
+    pub fn set_comments(&mut self, comments: bool)
+    {
+        self.options.comments = comments;
+    }
+
+    /// Takes the warnings given so far about tokens which were read anyway,
+    /// such as a wide character constant with more than one character.
+    pub fn take_warnings(&mut self) -> Vec<Diagnostic>
+    {
+        std::mem::replace(&mut self.warnings, Vec::new())
+    }
+
+    /// Sets the number of the line after the current one, for '#line'.
+    /// Tokens which have already been peeked are not affected.
+    pub fn set_line(&mut self, line: uint)
+    {
+        self.pos.line = line;
+    }
+
+    /// Reads the rest of the line as text, for directives such as '#error'
+    /// whose message doesn't have to be made of valid tokens.
+    /// Line splices are removed, comments are replaced by a space, the text is trimmed
+    /// and the new line is left to end the directive.
+    /// Tokens which have already been peeked are not affected.
+    pub fn read_rest_of_line(&mut self) -> Result<String,Diagnostic>
+    {
+        let mut text = String::new();
+
+        loop {
+            match (self.peek_char(), self.peek_char_n(1)) {
+                (Some('\n'), _) | (Some('\r'), _) | (None, _) => break,
+                (Some('/'), Some('/')) => {
+                    self.skip_line_comment();
+                    text.push(' ');
+                },
+                // a block comment may go on past the end of the line.
+                (Some('/'), Some('*')) => {
+                    try!(self.skip_block_comment());
+                    text.push(' ');
+                },
+                // comments don't start inside of a string literal or character constant.
+                (Some(quote), _) if (quote == '"') || (quote == '\'') => {
+                    let length = self.quoted_length(quote);
+
+                    for _ in range(0, length) {
+                        text.push(self.bump().unwrap());
+                    }
+                },
+                (Some(c), _) => {
+                    self.bump();
+                    text.push(c);
+                },
+            }
+        }
+
+        Ok(text.as_slice().trim().to_string())
+    }
+
+    /// Gets the number of characters in the quoted text at the current position,
+    /// including the quotes. An unmatched quote is a character on its own.
+    fn quoted_length(&self, quote: char) -> uint
+    {
+        let mut n = 1;
+
+        loop {
+            match self.peek_char_n(n) {
+                Some('\\') => { n += 2; },
+                Some(c) if c == quote => { return n + 1; },
+                Some('\n') | Some('\r') | None => { return 1; },
+                Some(..) => { n += 1; },
+            }
+        }
+    }
+
+    /// Peeks at the next token.
+    pub fn peek(&mut self) -> Option<Result<Token,Diagnostic>>
+    {
+        let val: Token = match self.stack.pop() {
+            Some(val) => val.clone(),
+            None => {
+                match self.next() {
+                    Some(val) => {
+                        match val {
+                            Ok(a) => a,
+                            err => { return Some(err); }
+                        }
+                    },
+                    None => { return None; },
+                }
+            }
+        };
+
+        self.stack.push(val.clone());
+        Some(Ok(val))
+    }
+
+    /// Checks whether a token has been peeked at, but not consumed yet.
+    pub fn has_peeked(&self) -> bool
+    {
+        !self.stack.is_empty()
+    }
+
+    /// Eats the next token, disregarding it.
+    pub fn eat(&mut self)
+    {
+        self.next();
+    }
+
+    /// Peeks at the n'th token from the current index.
+    pub fn peek_n(&mut self, n: uint) -> Option<Result<Token,Diagnostic>>
+    {
+        let mut read_elems = Vec::new();
+
+        for _ in range(0,n+1) {
+
+            match self.next() {
+                Some(e) => {
+                    match e {
+                        Ok(tok) => read_elems.push(tok),
+                        err => { return Some(err); }
+                    }
+                },
+                None => {
+                    break;
+                },
+            }
+        }
+
+        for read_char in read_elems.iter().rev() {
+            self.stack.push(read_char.clone());
+        }
+
+        match read_elems.last() {
+            Some(a) => Some(Ok(a.clone())),
+            None => None
+        }
+    }
+
+    /// Gets the position of the next character.
+    pub fn position(&mut self) -> Position
+    {
+        // line splices before a character aren't part of it.
+        loop {
+            match phases::splice_at(self.src, self.pos.offset, self.options.trigraphs) {
+                Some(next) => self.advance_to(next),
+                None => break,
+            }
+        }
+
+        self.pos.clone()
+    }
+
+    /// Peeks at the next character.
+    fn peek_char(&self) -> Option<char>
+    {
+        self.peek_char_n(0)
+    }
+
+    /// Peeks at the n'th character from the current position.
+    fn peek_char_n(&self, n: uint) -> Option<char>
+    {
+        let mut offset = self.pos.offset;
+
+        for _ in range(0, n) {
+            match phases::char_at(self.src, offset, self.options.trigraphs) {
+                Some((_, next)) => { offset = next; },
+                None => { return None; },
+            }
+        }
+
+        phases::char_at(self.src, offset, self.options.trigraphs).map(|(c, _)| c)
+    }
+
+    /// Consumes the next character, keeping track of where we are in the file.
+    fn bump(&mut self) -> Option<char>
+    {
+        match phases::char_at(self.src, self.pos.offset, self.options.trigraphs) {
+            Some((c, next)) => {
+                // if the character took up more source than it should have,
+                // we went through a splice or a trigraph.
+                if (next - self.pos.offset) != c.len_utf8() {
+                    self.dirty = true;
+                }
+
+                self.advance_to(next);
+                Some(c)
+            },
+            None => None,
+        }
+    }
+
+    /// Moves forward to a byte offset, keeping track of lines and columns.
+    fn advance_to(&mut self, offset: uint)
+    {
+        for c in self.src.slice(self.pos.offset, offset).chars() {
+            self.pos.advance(c);
+        }
+    }
+
+    /// Skips over any whitespace, and comments unless they are being given as tokens.
+    /// New lines are skipped too, unless we are in a directive.
+    fn skip_whitespace(&mut self) -> Result<(),Diagnostic>
+    {
+        loop {
+            let new_line = match (self.peek_char(), self.peek_char_n(1)) {
+                // '\r\n' is a new line.
+                (Some('\r'), Some('\n')) | (Some('\n'), _) => true,
+                // comments are replaced by a space, so they are whitespace.
+                (Some('/'), Some('/')) if !self.options.comments => {
+                    self.skip_line_comment();
+                    continue;
+                },
+                (Some('/'), Some('*')) if !self.options.comments => {
+                    try!(self.skip_block_comment());
+                    continue;
+                },
+                (Some(c), _) if c.is_whitespace() => false,
+                _ => break,
+            };
+
+            if new_line {
+                if self.mode == Mode::Directive {
+                    break;
+                }
+
+                self.line_start = true;
+            }
+
+            self.bump();
+        }
+
+        Ok(())
+    }
+
+    /// Reads any trivia at the current position, up to the next token, adding it to `pieces`.
+    /// If `stop_at_new_line` is set, we also stop at the end of the line.
+    /// On an error, `pieces` keeps everything before the piece which went wrong.
+    fn read_trivia(&mut self, stop_at_new_line: bool, pieces: &mut Vec<trivia::Piece>) -> Result<(),Diagnostic>
+    {
+        loop {
+            let start = self.pos.clone();
+
+            let kind = match phases::splice_at(self.src, start.offset, self.options.trigraphs) {
+                Some(next) => {
+                    self.advance_to(next);
+                    trivia::Kind::LineSplice
+                },
+                None => match (self.peek_char(), self.peek_char_n(1)) {
+                    (Some('/'), Some('/')) => {
+                        self.skip_line_comment();
+                        trivia::Kind::LineComment
+                    },
+                    (Some('/'), Some('*')) => {
+                        try!(self.skip_block_comment());
+                        trivia::Kind::BlockComment
+                    },
+                    (Some('\r'), Some('\n')) | (Some('\n'), _) if stop_at_new_line => break,
+                    (Some('\r'), Some('\n')) | (Some('\n'), _) => {
+                        if self.bump() == Some('\r') {
+                            self.bump();
+                        }
+
+                        self.line_start = true;
+                        trivia::Kind::NewLine
+                    },
+                    (Some(c), _) if c.is_whitespace() => {
+                        self.skip_raw_whitespace();
+                        trivia::Kind::Whitespace
+                    },
+                    _ => break,
+                },
+            };
+
+            pieces.push(trivia::Piece {
+                kind: kind,
+                text: self.src.slice(start.offset, self.pos.offset).to_string(),
+                span: self.span_from(start),
+            });
+        }
+
+        Ok(())
+    }
+
+    /// Skips whitespace without going past a line splice, so that splices
+    /// can be kept as trivia of their own.
+    fn skip_raw_whitespace(&mut self)
+    {
+        loop {
+            match phases::raw_char_at(self.src, self.pos.offset, self.options.trigraphs) {
+                Some(('\r', _)) if phases::new_line_at(self.src, self.pos.offset).is_some() => break,
+                Some(('\n', _)) => break,
+                Some((c, next)) if c.is_whitespace() => self.advance_to(next),
+                Some(..) | None => break,
+            }
+        }
+    }
+
+    /// Skips a '//' comment, leaving the new line which ends it.
+    fn skip_line_comment(&mut self)
+    {
+        loop {
+            match self.peek_char() {
+                Some('\r') if self.peek_char_n(1) == Some('\n') => break,
+                Some('\n') | None => break,
+                Some(..) => { self.bump(); },
+            }
+        }
+    }
+
+    /// Skips a '/* */' comment.
+    fn skip_block_comment(&mut self) -> Result<(),Diagnostic>
+    {
+        let start = self.pos.clone();
+
+        self.bump(); // skip '/'.
+        self.bump(); // skip '*'.
+
+        loop {
+            match self.peek_char() {
+                Some('*') if self.peek_char_n(1) == Some('/') => {
+                    self.bump();
+                    self.bump();
+                    return Ok(());
+                },
+                Some(..) => { self.bump(); },
+                None => {
+                    return Err(Diagnostic::error(Code::UnterminatedComment, "unterminated comment".to_string())
+                               .with_span(self.span_from(start)));
+                },
+            }
+        }
+    }
+
+    /// Creates a span from a starting position to the current position.
+    fn span_from(&self, start: Position) -> Span
+    {
+        Span::new(self.options.file.clone(), start, self.pos.clone())
+    }
+
+    /// Gets the spelling of everything read since a position.
+    /// This only copies if the text crosses a line splice or trigraph.
+    fn spelling_from(&self, start: &Position) -> CowString<'a>
+    {
+        let text = self.src.slice(start.offset, self.pos.offset);
+
+        if self.dirty {
+            Cow::Owned(phases::clean(text, self.options.trigraphs))
+        } else {
+            Cow::Borrowed(text)
+        }
+    }
+
+    /// Builds a lexeme out of everything read since a position.
+    fn lexeme_from(&self, kind: Kind, start: Position) -> Lexeme<'a>
+    {
+        Lexeme {
+            kind: kind,
+            spelling: self.spelling_from(&start),
+            span: self.span_from(start),
+            trivia: None,
+            line_start: false,
+            leading_space: false,
+        }
+    }
+
+    fn parse_identifier(&mut self) -> Result<Lexeme<'a>,Diagnostic>
+    {
+        let start = self.position();
+        let mut first = true;
+
+        loop {
+            match self.peek_char() {
+                // universal character names can be used anywhere in an identifier.
+                Some('\\') if self.is_universal_character_name() => {
+                    try!(self.parse_identifier_ucn(first));
+                },
+                Some(c) if first || identifier::is_valid_char(c, self.options.standard.clone()) => {
+                    // eat the character.
+                    self.bump();
+                },
+                Some(..) | None => break,
+            }
+
+            first = false;
+        }
+
+        // keywords keep their spelling, so that they can still be used as macro names.
+        let kind = match self.keywords.lookup(&*self.spelling_from(&start), &self.options.standard) {
+            Some(keyword) => Kind::Keyword(keyword),
+            None => Kind::Word,
+        };
+
+        Ok(self.lexeme_from(kind, start))
+    }
+
+    /// Checks whether a universal character name comes next.
+    fn is_universal_character_name(&self) -> bool
+    {
+        match (self.peek_char(), self.peek_char_n(1)) {
+            (Some('\\'), Some('u')) | (Some('\\'), Some('U')) => true,
+            _ => false,
+        }
+    }
+
+    /// Reads a universal character name inside an identifier, and checks that
+    /// the character it names is allowed there.
+    fn parse_identifier_ucn(&mut self, first: bool) -> Result<(),Diagnostic>
+    {
+        let start = self.position();
+
+        self.bump(); // skip '\\'.
+        let digit_count = match self.bump() {
+            Some('u') => 4u,
+            _ => 8u,
+        };
+
+        let mut value: u32 = 0;
+
+        for _ in range(0, digit_count) {
+            match self.peek_char() {
+                Some(d) if d.is_digit(16) => {
+                    self.bump();
+                    value = (value * 16) + (d.to_digit(16).unwrap() as u32);
+                },
+                _ => {
+                    return Err(Diagnostic::error(Code::InvalidUniversalCharacter,
+                                                 format!("incomplete universal character name, expected {} hex digits", digit_count))
+                                   .with_span(self.span_from(start)));
+                },
+            }
+        }
+
+        let c = match literal::universal_character(value) {
+            Ok(c) => c,
+            Err(diagnostic) => { return Err(diagnostic.with_span(self.span_from(start))); },
+        };
+
+        let allowed = match first {
+            true => identifier::is_valid_first_char(c, self.options.standard.clone()),
+            false => identifier::is_valid_char(c, self.options.standard.clone()),
+        };
+
+        match allowed {
+            true => Ok(()),
+            false => {
+                Err(Diagnostic::error(Code::InvalidIdentifier,
+                                      format!("'{}' (U+{:04X}) is not allowed in an identifier", c, value))
+                        .with_span(self.span_from(start)))
+            },
+        }
+    }
+
+    fn parse_pp_number(&mut self) -> Result<Lexeme<'a>,Diagnostic>
+    {
+        let start = self.position();
+
+        // read everything which could be part of the number. it isn't
+        // worked out whether it makes sense until after preprocessing, so
+        // '12x3x' is one bad number rather than a number followed by a word.
+        loop {
+            match self.peek_char() {
+                // exponents may be signed.
+                Some(c) if (c == 'e') | (c == 'E') | (c == 'p') | (c == 'P') => {
+                    self.bump();
+
+                    match self.peek_char() {
+                        Some(sign) if (sign == '+') | (sign == '-') => { self.bump(); },
+                        _ => (),
+                    }
+                },
+                Some(c) if identifier::is_valid_char(c, self.options.standard.clone()) | (c == '.') => {
+                    self.bump();
+                },
+                // digit separators.
+                Some('\'') if self.options.standard >= Standard::C23 => match self.peek_char_n(1) {
+                    Some(c) if identifier::is_valid_char(c, self.options.standard.clone()) => { self.bump(); },
+                    _ => break,
+                },
+                Some(..) | None => { break; }
+            }
+        }
+
+        Ok(self.lexeme_from(Kind::PpNumber, start))
+    }
+
+    /// Reads a header name, such as '<avr/io.h>' or '"header.h"'.
+    /// Gives nothing if the header name isn't closed on the same line,
+    /// in which case nothing is consumed.
+    fn parse_header_name(&mut self) -> Option<Lexeme<'a>>
+    {
+        let (close, delimiter) = match self.peek_char() {
+            Some('<') => ('>', HeaderDelimiter::AngleBrackets),
+            Some('"') => ('"', HeaderDelimiter::Quotes),
+            _ => { return None; },
+        };
+
+        let mut length = 1u;
+
+        loop {
+            match self.peek_char_n(length) {
+                Some(c) if c == close => break,
+                Some('\n') | Some('\r') | None => { return None; },
+                Some(..) => { length += 1; },
+            }
+        }
+
+        let start = self.position();
+
+        for _ in range(0, length + 1) {
+            self.bump();
+        }
+
+        Some(self.lexeme_from(Kind::HeaderName(delimiter), start))
+    }
+
+    /// Checks whether an encoding prefix such as 'u8' or 'L' starts a literal at the current position.
+    /// Gives back the encoding, and the length of the prefix.
+    fn encoding_prefix(&self) -> Option<(literal::Encoding, uint)>
+    {
+        let (prefix, length) = match (self.peek_char(), self.peek_char_n(1)) {
+            (Some('u'), Some('8')) => ("u8", 2u),
+            (Some('u'), _) => ("u", 1u),
+            (Some('U'), _) => ("U", 1u),
+            (Some('L'), _) => ("L", 1u),
+            _ => { return None; },
+        };
+
+        let quote = match self.peek_char_n(length) {
+            Some(quote) if (quote == '"') || (quote == '\'') => quote,
+            _ => { return None; },
+        };
+
+        // before these were added, the prefix would have been an identifier.
+        let supported = match prefix {
+            "L" => true,
+            "u8" if quote == '\'' => self.options.standard >= Standard::C23,
+            _ => self.options.standard >= Standard::C11,
+        };
+
+        match supported {
+            true => literal::Encoding::from_prefix(prefix).map(|encoding| (encoding, length)),
+            false => None,
+        }
+    }
+
+    /// Parses a string literal or character constant, starting at its encoding prefix, if any.
+    fn parse_quoted_literal(&mut self, encoding: literal::Encoding) -> Result<Lexeme<'a>,Diagnostic>
+    {
+        let start = self.position();
+
+        // the prefix is all ASCII, so its length in chars is its length in bytes.
+        let prefix_length = encoding.prefix().len();
+
+        for _ in range(0, prefix_length) {
+            self.bump();
+        }
+
+        let quote = self.bump().unwrap();
+
+        let description = match quote {
+            '"' => "string literal",
+            _ => "character constant",
+        };
+
+        loop {
+            match self.peek_char() {
+                Some(c) if c == quote => {
+                    self.bump();
+                    break;
+                },
+                Some('\\') => {
+                    self.bump();
+
+                    // the escaped character can't end the literal, unless it is the end of the line.
+                    match self.peek_char() {
+                        Some('\n') | Some('\r') | None => (),
+                        Some(..) => { self.bump(); },
+                    }
+                },
+                Some('\n') | Some('\r') | None => {
+                    return Err(Diagnostic::error(Code::UnterminatedLiteral, format!("unterminated {}", description))
+                                   .with_span(self.span_from(start))
+                                   .with_note(format!("missing terminating {} character", quote)));
+                },
+                Some(..) => { self.bump(); },
+            }
+        }
+
+        let literal = {
+            let spelling = self.spelling_from(&start);
+            let body = spelling.slice(prefix_length + 1, spelling.len() - 1);
+
+            match literal::decode(body, encoding) {
+                Ok(literal) => literal,
+                Err(diagnostic) => { return Err(diagnostic.with_span(self.span_from(start))); },
+            }
+        };
+
+        let kind = match quote {
+            '"' => Kind::StringLiteral(literal),
+            _ => {
+                if literal.units.is_empty() {
+                    return Err(Diagnostic::error(Code::EmptyCharacterConstant, format!("empty {}", description))
+                                   .with_span(self.span_from(start)));
+                }
+
+                // plain character constants may hold more than one code unit.
+                match literal.encoding == literal::Encoding::Plain {
+                    true => Kind::CharLiteral(literal),
+                    false => {
+                        // every character has to fit in a single code unit.
+                        if literal.code_units.len() > literal.units.len() {
+                            return Err(Diagnostic::error(Code::CharacterOutOfRange,
+                                                         format!("character too large for {} {}",
+                                                                 literal.encoding.prefix(), description))
+                                           .with_span(self.span_from(start)));
+                        }
+
+                        // any other constant with more than one character keeps the last of them, as GCC does.
+                        if literal.units.len() > 1 {
+                            self.warnings.push(Diagnostic::warning(Code::CharacterOutOfRange,
+                                                                   format!("{} {} too long for its type",
+                                                                           literal.encoding.prefix(), description))
+                                                   .with_span(self.span_from(start.clone())));
+                        }
+
+                        Kind::CharLiteral(literal::Literal {
+                            encoding: literal.encoding.clone(),
+                            units: vec![literal.units.last().unwrap().clone()],
+                            code_units: vec![*literal.code_units.last().unwrap()],
+                        })
+                    },
+                }
+            },
+        };
+
+        Ok(self.lexeme_from(kind, start))
+    }
+
+    fn parse_possible_punctuator(&mut self) -> Result<Lexeme<'a>,Diagnostic>
+    {
+        let start = self.position();
+        let found = self.punctuators.longest_match(&self.options.standard, |n| self.peek_char_n(n));
+
+        match found {
+            Some((punctuator, length)) => {
+                for _ in range(0, length) {
+                    self.bump();
+                }
+
+                Ok(self.lexeme_from(Kind::Punctuator(punctuator), start))
+            },
+            // no matches.
+            None => {
+                // skip over the character, so that the caller can carry on.
+                let c = self.bump().unwrap();
+
+                Err(Diagnostic::error(Code::UnknownCharacter, format!("unknown character '{}'", c))
+                        .with_span(self.span_from(start)))
+            },
+        }
+    }
+
+    /// Reads the next lexeme straight from the source.
+    fn read_lexeme(&mut self) -> Option<Result<Lexeme<'a>,Diagnostic>>
+    {
+        let result = self.read_lexeme_with_trivia();
+
+        match result {
+            Some(Ok(ref lexeme)) => self.update_context(lexeme),
+            _ => (),
+        }
+
+        result
+    }
+
+    /// Keeps track of whether a header name may come next.
+    /// They may only follow '#include', '#include_next' or '__has_include('.
+    fn update_context(&mut self, lexeme: &Lexeme<'a>)
+    {
+        let context = match (&self.context, &lexeme.kind) {
+            (_, &Kind::Punctuator(Punctuator::Hash)) if lexeme.line_start => Context::Directive,
+            (context, &Kind::LineComment) | (context, &Kind::BlockComment) => context.clone(),
+            (&Context::Directive, &Kind::Word) => match &*lexeme.spelling {
+                "include" | "include_next" => Context::HeaderName,
+                _ => Context::Other,
+            },
+            (&Context::HasInclude, &Kind::Punctuator(Punctuator::LeftParenthesis)) => Context::HeaderName,
+            (_, &Kind::Word) => match &*lexeme.spelling {
+                "__has_include" | "__has_include_next" => Context::HasInclude,
+                _ => Context::Other,
+            },
+            _ => Context::Other,
+        };
+
+        self.context = context;
+    }
+
+    /// Reads the next lexeme, along with its trivia if it is being kept.
+    fn read_lexeme_with_trivia(&mut self) -> Option<Result<Lexeme<'a>,Diagnostic>>
+    {
+        if self.finished {
+            return None;
+        }
+
+        let start = self.pos.clone();
+        let in_directive = self.mode == Mode::Directive;
+
+        let leading = match self.options.trivia {
+            true => {
+                let mut leading = Vec::new();
+
+                match self.read_trivia(in_directive, &mut leading) {
+                    Ok(()) => leading,
+                    Err(diagnostic) => { return Some(self.recover_in_trivia(diagnostic, leading, start)); },
+                }
+            },
+            false => match self.skip_whitespace() {
+                Ok(()) => Vec::new(),
+                Err(diagnostic) => { return Some(self.recover(diagnostic, start)); },
+            },
+        };
+
+        let line_start = self.line_start;
+        // a line splice is gone before tokens are formed, so it isn't whitespace on its own.
+        let skipped_space = match self.options.trivia {
+            true => leading.iter().any(|piece| piece.kind != trivia::Kind::LineSplice),
+            false => self.pos.offset != start.offset,
+        };
+
+        let leading_space = skipped_space || self.after_comment;
+
+        // the end of a directive is always given, even at the end of the file.
+        if (self.mode == Mode::Code) && self.peek_char().is_none() {
+            self.finished = true;
+
+            if !self.options.trivia {
+                return None;
+            }
+
+            self.dirty = false;
+
+            let mut lexeme = self.lexeme_from(Kind::EndOfFile, self.pos.clone());
+            lexeme.line_start = line_start;
+            lexeme.leading_space = leading_space;
+            lexeme.trivia = Some(Trivia {
+                leading: leading,
+                text: String::new(),
+                trailing: Vec::new(),
+            });
+
+            return Some(Ok(lexeme));
+        }
+
+        let token_start = self.pos.clone();
+
+        let mut lexeme = match self.read_token() {
+            Ok(lexeme) => lexeme,
+            Err(diagnostic) => match self.recover(diagnostic, token_start) {
+                Ok(lexeme) => lexeme,
+                Err(diagnostic) => {
+                    // whatever went wrong still took up the start of the line, so a '#' after it isn't a directive.
+                    self.line_start = false;
+                    self.after_comment = false;
+
+                    return Some(Err(diagnostic));
+                },
+            },
+        };
+
+        lexeme.line_start = line_start;
+        lexeme.leading_space = leading_space;
+
+        // a comment doesn't stop the next token from being the first on its line.
+        self.line_start = match lexeme.kind {
+            Kind::EndOfDirective => true,
+            Kind::LineComment | Kind::BlockComment => line_start,
+            _ => false,
+        };
+
+        self.after_comment = match lexeme.kind {
+            Kind::LineComment | Kind::BlockComment => true,
+            _ => false,
+        };
+
+        if self.options.trivia {
+            // trailing trivia stops at the end of the line, so the end of a directive never has any.
+            let mut trailing = Vec::new();
+
+            match lexeme.kind {
+                Kind::EndOfDirective => (),
+                _ => match self.read_trivia(true, &mut trailing) {
+                    Ok(()) => (),
+                    // the token is fine, so the piece which went wrong is left
+                    // to be read again as leading trivia of the next token.
+                    Err(..) => {
+                        self.pos = trailing.last().map(|piece| piece.span.end.clone()).unwrap_or(lexeme.span.end.clone());
+                    },
+                },
+            }
+
+            lexeme.trivia = Some(Trivia {
+                leading: leading,
+                text: self.src.slice(lexeme.span.start.offset, lexeme.span.end.offset).to_string(),
+                trailing: trailing,
+            });
+        }
+
+        Some(Ok(lexeme))
+    }
+
+    /// Turns an error into an error token covering everything read since `start`,
+    /// if we are recovering from errors.
+    /// At least one character is always skipped, so that we can't get stuck.
+    fn recover(&mut self, diagnostic: Diagnostic, start: Position) -> Result<Lexeme<'a>,Diagnostic>
+    {
+        if !self.options.recover {
+            return Err(diagnostic);
+        }
+
+        if self.pos.offset == start.offset {
+            self.bump();
+        }
+
+        // the error token may cover a line splice or trigraph.
+        self.dirty = true;
+
+        let span = self.span_from(start.clone());
+        Ok(self.lexeme_from(Kind::Error(diagnostic.or_span(span)), start))
+    }
+
+    /// Turns an error in leading trivia into an error token, if we are recovering from errors.
+    /// The trivia read before the error is kept with the token, so that no source is lost.
+    fn recover_in_trivia(&mut self, diagnostic: Diagnostic, pieces: Vec<trivia::Piece>, start: Position)
+        -> Result<Lexeme<'a>,Diagnostic>
+    {
+        let error_start = pieces.last().map(|piece| piece.span.end.clone()).unwrap_or(start);
+        let mut lexeme = try!(self.recover(diagnostic, error_start));
+
+        lexeme.trivia = Some(Trivia {
+            leading: pieces,
+            text: self.src.slice(lexeme.span.start.offset, lexeme.span.end.offset).to_string(),
+            trailing: Vec::new(),
+        });
+
+        Ok(lexeme)
+    }
+
+    /// Reads the token at the current position, just after any whitespace.
+    fn read_token(&mut self) -> Result<Lexeme<'a>,Diagnostic>
+    {
+        let start = self.position();
+        self.dirty = false;
+
+        // new lines are only given inside of directives, and end them.
+        let first_char = match (self.peek_char(), self.peek_char_n(1)) {
+            (Some('\r'), Some('\n')) | (Some('\n'), _) | (None, _) => {
+                if self.bump() == Some('\r') {
+                    self.bump();
+                }
+
+                self.mode = Mode::Code;
+                return Ok(self.lexeme_from(Kind::EndOfDirective, start));
+            },
+            (Some(first_char), _) => first_char,
+        };
+
+        if self.options.comments && (first_char == '/') {
+            match self.peek_char_n(1) {
+                Some('/') => {
+                    self.skip_line_comment();
+                    return Ok(self.lexeme_from(Kind::LineComment, start));
+                },
+                Some('*') => {
+                    try!(self.skip_block_comment());
+                    return Ok(self.lexeme_from(Kind::BlockComment, start));
+                },
+                _ => (),
+            }
+        }
+
+        match self.encoding_prefix() {
+            Some((encoding, _)) => { return self.parse_quoted_literal(encoding); },
+            None => (),
+        }
+
+        if self.context == Context::HeaderName {
+            match self.parse_header_name() {
+                Some(lexeme) => { return Ok(lexeme); },
+                None => (),
+            }
+        }
+
+        if identifier::is_valid_first_char(first_char, self.options.standard.clone()) || self.is_universal_character_name() {
+            self.parse_identifier()
+        } else if first_char.is_digit(10) {
+            self.parse_pp_number()
+        } else if (first_char == '.') && self.peek_char_n(1).map(|c| c.is_digit(10)).unwrap_or(false) {
+            // a number like '.5'.
+            self.parse_pp_number()
+        } else if (first_char == '"') || (first_char == '\'') {
+            self.parse_quoted_literal(literal::Encoding::Plain)
+        } else {
+            self.parse_possible_punctuator()
+        }
+    }
+}
+
+impl<'a> Iterator<Result<Token,Diagnostic>> for Tokenizer<'a>
+{
+    /// Gets the next token.
+    fn next(&mut self) -> Option<Result<Token,Diagnostic>>
+    {
+        // if we have peeked data on the stack, retrieve it.
+        match self.stack.pop() {
+            Some(tok) => { return Some(Ok(tok)); },
+            None => (),
+        };
+
+        self.read_lexeme().map(|result| result.map(|lexeme| lexeme.into_token()))
+    }
+}
+
+/// An iterator over the lexemes of a source file.
+pub struct Lexemes<'a>
+{
+    tokenizer: Tokenizer<'a>,
+}
+
+impl<'a> Iterator<Result<Lexeme<'a>,Diagnostic>> for Lexemes<'a>
+{
+    fn next(&mut self) -> Option<Result<Lexeme<'a>,Diagnostic>>
+    {
+        self.tokenizer.read_lexeme()
+    }
+}
+
+#[cfg(test)]
+mod tests
+{
+    use token::{Punctuator,Tokenizer};
+    use token::Kind as TokenKind;
+    use token::trivia::Kind;
+    use diagnostic::Code;
+    use super::{Mode,Options};
+
+    /// Tokenizes a source with trivia, switching into directive mode after a '#' at the start
+    /// of a line as the preprocessor would, and puts the source text of the tokens back together.
+    fn round_trip(src: &str, trigraphs: bool) -> String
+    {
+        let options = Options {
+            trivia: true,
+            trigraphs: trigraphs,
+            .. Options::new()
+        };
+
+        let mut tokenizer = Tokenizer::with_options(src, options);
+        let mut result = String::new();
+
+        loop {
+            let token = match tokenizer.next() {
+                Some(token) => token.unwrap(),
+                None => break,
+            };
+
+            if token.line_start && token.is_punctuator(Punctuator::Hash) {
+                tokenizer.set_mode(Mode::Directive);
+            }
+
+            result.push_str(token.source_text().unwrap().as_slice());
+        }
+
+        result
+    }
+
+    #[test]
+    fn trivia_round_trip()
+    {
+        let sources = [
+            "",
+            "   \n\t\n",
+            "int a;",
+            "int  a\t=\t1 ;  \n",
+            "a // line comment\nb /* block\n comment */ c\n",
+            "/* only a comment */",
+            "x = y /* before the end */ ;// no new line",
+            "a\r\nb\r\n\r\n",
+            "lo\\\nng = 1\\\n;\n",
+            "a \\\n b\n",
+            "#define A(x) x /* c */ + 1 \\\n  + 2\n#include <stdio.h>\n  # if A(1)\nint b;\n#endif\n",
+            "#\n# /* empty */ \n",
+            "s = \"str\\\"ing\" 'c' L\"wide\";\n",
+            "<: :> <% %> %: %:%:\n",
+            "1.5e+3 0x1p-2 ... ->\n",
+        ];
+
+        for source in sources.iter() {
+            assert_eq!(round_trip(*source, false).as_slice(), *source);
+        }
+    }
+
+    #[test]
+    fn trivia_round_trip_with_trigraphs()
+    {
+        let source = "??=define A ??/\n 1\na??(0??) = '??/n';\n";
+
+        assert_eq!(round_trip(source, true).as_slice(), source);
+    }
+
+    #[test]
+    fn trivia_pieces()
+    {
+        let options = Options {
+            trivia: true,
+            .. Options::new()
+        };
+
+        let tokens: Vec<_> = Tokenizer::with_options("a /* b */\\\n c\n", options).map(|token| token.unwrap()).collect();
+
+        let a = tokens[0].trivia.as_ref().unwrap();
+        assert!(a.leading.is_empty());
+        assert_eq!(a.text.as_slice(), "a");
+        assert_eq!(a.trailing.iter().map(|piece| piece.kind.clone()).collect::<Vec<_>>(),
+                   vec![Kind::Whitespace, Kind::BlockComment, Kind::LineSplice, Kind::Whitespace]);
+
+        // trailing trivia stops at the end of the line.
+        let c = tokens[1].trivia.as_ref().unwrap();
+        assert!(c.leading.is_empty());
+        assert!(c.trailing.is_empty());
+
+        // the end of the source is given as a token of its own, so that trivia after the last token isn't lost.
+        assert_eq!(tokens.len(), 3);
+        let end = tokens[2].trivia.as_ref().unwrap();
+        assert_eq!(end.leading.iter().map(|piece| piece.kind.clone()).collect::<Vec<_>>(), vec![Kind::NewLine]);
+        assert_eq!(end.text.as_slice(), "");
+    }
+    #[test]
+    fn wide_character_constants()
+    {
+        let mut tokenizer = Tokenizer::new("L'a' L'ab' U'xyz' u'\\x41\\x42'");
+
+        for &expected in ['a', 'b', 'z', 'B'].iter() {
+            match tokenizer.next().unwrap().unwrap().kind {
+                TokenKind::CharLiteral(literal) => {
+                    assert_eq!(literal.code_units, vec![expected as u32]);
+                },
+                kind => panic!("expected a character constant, got {}", kind),
+            }
+        }
+
+        // all but the first are too long, and keep their last character.
+        let warnings = tokenizer.take_warnings();
+        assert_eq!(warnings.len(), 3);
+        assert!(warnings.iter().all(|warning| !warning.is_error() && (warning.code == Code::CharacterOutOfRange)));
+        assert!(tokenizer.take_warnings().is_empty());
+
+        // plain character constants may hold more than one character.
+        let mut tokenizer = Tokenizer::new("'ab'");
+        assert!(tokenizer.next().unwrap().is_ok());
+        assert!(tokenizer.take_warnings().is_empty());
+    }
+
+    #[test]
+    fn wide_character_too_large()
+    {
+        // a character which needs two UTF-16 code units, or a value which doesn't fit, is still an error.
+        for source in ["u'\\U0001F600'", "u'a\\U0001F600'", "u'\\x12345'"].iter() {
+            let result = Tokenizer::new(*source).next().unwrap();
+            assert_eq!(result.unwrap_err().code, Code::CharacterOutOfRange);
+        }
+    }
+    #[test]
+    fn recovery()
+    {
+        let options = Options {
+            recover: true,
+            .. Options::new()
+        };
+
+        let tokens: Vec<_> = Tokenizer::with_options("a @ b ` c \\ d", options).map(|token| token.unwrap()).collect();
+
+        let values: Vec<&str> = tokens.iter().map(|token| token.value.as_slice()).collect();
+        assert_eq!(values, vec!["a", "@", "b", "`", "c", "\\", "d"]);
+
+        for (i, token) in tokens.iter().enumerate() {
+            match token.kind {
+                TokenKind::Error(ref diagnostic) => {
+                    assert!(i % 2 == 1);
+                    assert_eq!(diagnostic.code, Code::UnknownCharacter);
+                },
+                _ => assert!(i % 2 == 0),
+            }
+        }
+    }
+
+    #[test]
+    fn recovery_keeps_trivia()
+    {
+        let options = Options {
+            recover: true,
+            trivia: true,
+            .. Options::new()
+        };
+
+        let source = "a @ /* x */ b\n `\n";
+        let text: String = Tokenizer::with_options(source, options)
+            .map(|token| token.unwrap().source_text().unwrap())
+            .collect();
+
+        assert_eq!(text.as_slice(), source);
+    }
+
+    #[test]
+    fn error_ends_line_start()
+    {
+        // without recovery the error is given, and the tokenizer carries on after it.
+        let mut tokenizer = Tokenizer::new("@ #x\n#y");
+
+        assert_eq!(tokenizer.next().unwrap().unwrap_err().code, Code::UnknownCharacter);
+
+        let hash = tokenizer.next().unwrap().unwrap();
+        assert!(hash.is_punctuator(Punctuator::Hash));
+        assert!(!hash.line_start);
+
+        assert_eq!(tokenizer.next().unwrap().unwrap().value.as_slice(), "x");
+        assert!(tokenizer.next().unwrap().unwrap().line_start);
+    }
+}
@@ -0,0 +1,60 @@
+
+use location::Span;
+
+/// The kind of a piece of trivia.
+#[deriving(Clone,PartialEq,Eq,Show)]
+pub enum Kind
+{
+    Whitespace,
+    LineComment,
+    BlockComment,
+    /// A backslash-newline between two tokens.
+    LineSplice,
+    /// A new line outside of a preprocessing directive.
+    NewLine,
+}
+
+/// A piece of source which isn't part of any token.
+#[deriving(Clone,Show)]
+pub struct Piece
+{
+    pub kind: Kind,
+    /// The text exactly as it was written.
+    pub text: String,
+    pub span: Span,
+}
+
+/// Everything around a token which isn't part of it.
+///
+/// Leading trivia is everything between the previous line (or token, in a directive) and this token.
+/// Trailing trivia is everything after the token, up to the next token or the end of the line.
+/// Putting `leading`, `text` and `trailing` together for every token gives back the source exactly.
+#[deriving(Clone,Show)]
+pub struct Trivia
+{
+    pub leading: Vec<Piece>,
+    /// The text of the token exactly as it was written, including any line splices.
+    pub text: String,
+    pub trailing: Vec<Piece>,
+}
+
+impl Trivia
+{
+    /// Gets the source text covered by the token and its trivia.
+    pub fn source_text(&self) -> String
+    {
+        let mut result = String::new();
+
+        for piece in self.leading.iter() {
+            result.push_str(piece.text.as_slice());
+        }
+
+        result.push_str(self.text.as_slice());
+
+        for piece in self.trailing.iter() {
+            result.push_str(piece.text.as_slice());
+        }
+
+        result
+    }
+}